@@ -0,0 +1,134 @@
+//! Batch preprocessing and postprocessing without a model. The stub output is a
+//! deterministic `[batch, rows, 6]` array shaped like the MegaDetector v5 output.
+
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use image::metadata::Orientation;
+use ndarray::{s, Array3, Array4, Axis};
+
+use md5rs::detect::{batch_array, fill_batch, frame_boxes};
+use md5rs::letterbox::Letterbox;
+use md5rs::media::Frame;
+use md5rs::stamp::StampMask;
+use md5rs::utils::{Bbox, FileItem, InputLayout, InputTensor, PadAlign};
+
+const IMGSZ: usize = 1280;
+const BATCH: usize = 4;
+const ROWS: usize = 102000;
+const CONF_THRES: f32 = 0.2;
+
+fn frames() -> Vec<Frame> {
+    (0..BATCH)
+        .map(|i| Frame {
+            file: FileItem::new(i, 0, PathBuf::from(format!("{}.jpg", i)), None),
+            data: Array3::from_shape_fn((IMGSZ, IMGSZ, 3), |(y, x, c)| (x + y + c) as u8),
+            width: 1920,
+            height: 1080,
+            letterbox: Letterbox::new(
+                1920,
+                1080,
+                &StampMask::default(),
+                IMGSZ as u32,
+                PadAlign::Center,
+            ),
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: None,
+            shoot_time: None,
+            time_source: None,
+            orientation: Orientation::NoTransforms,
+            metadata: Default::default(),
+        })
+        .collect()
+}
+
+/// Stub model output with one row in a thousand above the confidence threshold
+fn stub_output() -> Array3<f32> {
+    Array3::from_shape_fn((BATCH, ROWS, 6), |(b, r, c)| match c {
+        0 | 1 => ((r * 7 + b) % IMGSZ) as f32,
+        2 | 3 => ((r * 7 + b) % IMGSZ + 32) as f32,
+        4 if r % 1000 == 0 => 0.9,
+        4 => 0.01,
+        _ => (r % 3) as f32,
+    })
+}
+
+/// Postprocessing before buffers were reused: transpose, then collect every row
+fn naive_boxes(output: &Array3<f32>, frames: &[Frame]) -> Vec<Vec<Bbox>> {
+    let output = output.t().into_owned();
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            let output = output.slice(s![.., .., i]);
+            let mut boxes = vec![];
+            for row in output.axis_iter(Axis(1)) {
+                let row: Vec<_> = row.iter().copied().collect();
+                if row[4] < CONF_THRES {
+                    continue;
+                }
+                let (x1, y1) = frame.letterbox.input_to_image(row[0], row[1]);
+                let (x2, y2) = frame.letterbox.input_to_image(row[2], row[3]);
+                boxes.push(Bbox {
+                    class: row[5] as usize,
+                    score: row[4],
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                });
+            }
+            boxes
+        })
+        .collect()
+}
+
+fn bench_preprocess(c: &mut Criterion) {
+    let frames = frames();
+    let input = InputTensor::default();
+    let scale = input.scale();
+    let mut group = c.benchmark_group("preprocess");
+    group.sample_size(20);
+    group.bench_function("allocate", |b| {
+        b.iter(|| black_box(batch_array(&frames, &input, IMGSZ, |x| x as f32 * scale)))
+    });
+    let mut buffer = Array4::<f32>::zeros(input.shape(BATCH, IMGSZ));
+    group.bench_function("reuse", |b| {
+        b.iter(|| {
+            fill_batch(buffer.view_mut(), &frames, InputLayout::Nchw, |x| {
+                x as f32 * scale
+            });
+            black_box(&buffer);
+        })
+    });
+    group.finish();
+}
+
+fn bench_postprocess(c: &mut Criterion) {
+    let frames = frames();
+    let output = stub_output();
+    let mut group = c.benchmark_group("postprocess");
+    group.sample_size(20);
+    group.bench_function("transpose_collect", |b| {
+        b.iter(|| black_box(naive_boxes(&output, &frames)))
+    });
+    group.bench_function("frame_boxes", |b| {
+        b.iter_batched(
+            || output.view(),
+            |output| {
+                let boxes: Vec<_> = output
+                    .outer_iter()
+                    .zip(&frames)
+                    .map(|(output, frame)| frame_boxes(output, frame, CONF_THRES))
+                    .collect();
+                black_box(boxes)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_preprocess, bench_postprocess);
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 import argparse
+import json
 import os
 import shutil
 import sys
@@ -30,9 +31,31 @@ def date_parser(s):
     return pd.to_datetime(s, format="%Y-%m-%d %H:%M:%S")
 
 
+def export_roots(result: Path) -> list[Path]:
+    # Folders processed into the export, from its checkpoint header or its root column
+    header = result.with_name(f".{result.name}.header.json")
+    if header.exists():
+        header = json.loads(header.read_text(encoding="utf-8"))
+        # Headers written before multiple folders were supported have one root
+        roots = header.get("roots", header.get("root"))
+        if isinstance(roots, str):
+            roots = [roots]
+        if roots:
+            return [Path(root) for root in roots]
+    if result.suffix.lower() == ".csv":
+        df = pd.read_csv(result, encoding="utf-8", usecols=lambda column: column == "root")
+    else:
+        df = pd.read_json(result, orient="records", encoding="utf-8")
+    if "root" in df.columns:
+        roots = [root for root in df["root"].dropna().unique() if root]
+        if roots:
+            return [Path(root) for root in roots]
+    return [result.parent]
+
+
 class Cluster:
-    def __init__(self, target: Path) -> None:
-        self.target = target
+    def __init__(self, targets: list[Path]) -> None:
+        self.targets = targets
 
     @staticmethod
     def create_folders(folder_path: Path) -> None:
@@ -64,11 +87,22 @@ class Cluster:
         df = pd.read_csv(
             csv,
             encoding="utf-8",
-            usecols=range(9),
+            usecols=lambda column: column
+            in ["folder_id", "file_id", "file_path", "root", "shoot_time", "frame_index", "total_frames", "label", "error"],
         )
         df["label"] = df["label"].apply(lambda x: x.split(";") if isinstance(x, str) else None)
         return df
 
+    @staticmethod
+    def resolve_paths(df: pd.DataFrame) -> pd.DataFrame:
+        # Exports written with --relative-paths have file paths relative to their root
+        if "root" in df.columns:
+            df["file_path"] = [
+                str(Path(root) / path) if isinstance(root, str) and root else path
+                for root, path in zip(df["root"], df["file_path"])
+            ]
+        return df
+
     def parse_json(self, json_path: Path) -> pd.DataFrame:
         df = pd.read_json(json_path, orient="records", encoding="utf-8")
         return df
@@ -141,6 +175,7 @@ class Cluster:
             print(self.df.head())
         else:
             self.df = self.parse_csv(result)
+        self.df = self.resolve_paths(self.df)
         self.df["label"] = self.df["label"].apply(lambda x: self.get_label(x))
         self.df = merge_frames(self.df)
         self.df = self.df[self.df["label"].notnull()]
@@ -307,20 +342,21 @@ class Cluster:
 
     def redo_orgnize(self):
         self.total_file_count = 0
-        for root, _, files in os.walk(self.target):
-            for file in files:
-                file_path = Path(root) / file
-                if str(file_path.parts[-2]) in ["Animal", "Person", "Vehicle", "Blank"]:
-                    dest_path = Path(root).parent / file
-                    try:
-                        shutil.move(file_path, dest_path)
-                        logger.info(f"Moving {file_path} to {dest_path}")
-                    except Exception as e:
-                        logger.error(f"Failed to move file: {e}")
+        for target in self.targets:
+            for root, _, files in os.walk(target):
+                for file in files:
+                    file_path = Path(root) / file
+                    if str(file_path.parts[-2]) in ["Animal", "Person", "Vehicle", "Blank"]:
+                        dest_path = Path(root).parent / file
+                        try:
+                            shutil.move(file_path, dest_path)
+                            logger.info(f"Moving {file_path} to {dest_path}")
+                        except Exception as e:
+                            logger.error(f"Failed to move file: {e}")
 
 
 def main(result: Path, redo: bool = False, guess: bool = False):
-    cluster = Cluster(result.parent)
+    cluster = Cluster(export_roots(result))
     if redo:
         cluster.redo_orgnize()
     else:
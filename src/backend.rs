@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use half::f16;
+use ndarray::{Array2, CowArray, Ix3, IxDyn};
+use ort::{DynValue, Session, SessionOutputs};
+use serde::Deserialize;
+
+use crate::detect::{frame_boxes, DetectConfig, InputBuffer};
+use crate::media::Frame;
+use crate::utils::Bbox;
+
+/// Runs detection on a batch of frames for the detect workers
+pub trait InferenceBackend {
+    /// Boxes of each frame with score above `conf_thres`, in image pixels and before NMS
+    fn detect(&mut self, frames: &[Frame], conf_thres: f32) -> Result<Vec<Vec<Bbox>>>;
+}
+
+/// ONNX Runtime session with its reusable input tensor
+pub struct OrtBackend {
+    session: Session,
+    buffer: InputBuffer,
+}
+
+impl OrtBackend {
+    pub fn new(session: Session, config: &DetectConfig) -> Result<Self> {
+        let buffer = InputBuffer::new(config.input, config.batch_size, config.target_size)?;
+        Ok(OrtBackend { session, buffer })
+    }
+}
+
+impl InferenceBackend for OrtBackend {
+    fn detect(&mut self, frames: &[Frame], conf_thres: f32) -> Result<Vec<Vec<Bbox>>> {
+        let input = self.buffer.fill(frames)?;
+        let outputs: SessionOutputs = self.session.run(vec![("images", input)])?;
+        let output = extract_output(&outputs["output0"])?;
+        let output = output.view().into_dimensionality::<Ix3>()?; //[batch, 102000, 6]
+        Ok(frames
+            .iter()
+            .zip(output.outer_iter())
+            .map(|(frame, output)| frame_boxes(output, frame, conf_thres))
+            .collect())
+    }
+}
+
+/// Model output as `f32`, borrowed unless FP16 outputs need converting
+fn extract_output(output: &DynValue) -> Result<CowArray<'_, f32, IxDyn>> {
+    match output.try_extract_tensor::<f32>() {
+        Ok(tensor) => Ok(CowArray::from(tensor)),
+        Err(_) => Ok(CowArray::from(
+            output.try_extract_tensor::<f16>()?.mapv(f32::from),
+        )),
+    }
+}
+
+/// Deterministic backend returning scripted boxes instead of running a model,
+/// so the pipeline can be tested without ONNX Runtime or model files.
+/// Boxes are looked up by file name, then by `default`. Like model outputs they are in model
+/// input pixels and mapped back to the image through the frame letterbox.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockBackend {
+    #[serde(default)]
+    pub default: Vec<Bbox>,
+    #[serde(default)]
+    pub files: HashMap<String, Vec<Bbox>>,
+}
+
+impl MockBackend {
+    /// Load a JSON script of `{"default": [bbox], "files": {"name.jpg": [bbox]}}`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    fn detect(&mut self, frames: &[Frame], conf_thres: f32) -> Result<Vec<Vec<Bbox>>> {
+        Ok(frames
+            .iter()
+            .map(|frame| {
+                let name = frame.file.file_path.file_name().and_then(|n| n.to_str());
+                let boxes = name
+                    .and_then(|name| self.files.get(name))
+                    .unwrap_or(&self.default);
+                let rows = boxes
+                    .iter()
+                    .flat_map(|bbox| {
+                        let class = bbox.class as f32;
+                        [bbox.x1, bbox.y1, bbox.x2, bbox.y2, bbox.score, class]
+                    })
+                    .collect();
+                let output = Array2::from_shape_vec((boxes.len(), 6), rows).unwrap();
+                frame_boxes(output.view(), frame, conf_thres)
+            })
+            .collect())
+    }
+}
@@ -4,24 +4,29 @@ use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
-use ndarray::{s, Array4, Axis};
-use ort::{inputs, ExecutionProviderDispatch, Session, SessionOutputs};
+use half::f16;
+use ndarray::{Array4, ArrayView2, ArrayViewMut4, Ix4};
+use ort::{ExecutionProviderDispatch, Session, SessionInputValue, Tensor};
 use tracing::{debug, info, instrument, warn};
 
+use crate::backend::{InferenceBackend, OrtBackend};
 use crate::export::ExportFrame;
 use crate::media::{ArrayItem, Frame};
-use crate::utils::{nms, Bbox, Ep, EpDict};
+use crate::utils::{nms, Bbox, Ep, EpDict, InputDtype, InputLayout, InputTensor};
+use crate::BoxOrientation;
 
 #[derive(Clone, Debug)]
 pub struct DetectConfig {
     pub device: String,
     pub model_path: PathBuf,
     pub target_size: usize,
+    pub input: InputTensor,
     pub class_map: HashMap<usize, String>,
     pub conf_thres: f32,
     pub iou_thres: f32,
+    pub box_orientation: BoxOrientation,
     pub batch_size: usize,
     pub timeout: usize,
 }
@@ -50,18 +55,9 @@ pub fn detect_worker(
                             .with_engine_cache_path("./models")
                             .with_timing_cache(true)
                             .with_fp16(true)
-                            .with_profile_min_shapes(format!(
-                                "images:1x3x{}x{}",
-                                config.target_size, config.target_size
-                            ))
-                            .with_profile_opt_shapes(format!(
-                                "images:2x3x{}x{}",
-                                config.target_size, config.target_size
-                            ))
-                            .with_profile_max_shapes(format!(
-                                "images:5x3x{}x{}",
-                                config.target_size, config.target_size
-                            ))
+                            .with_profile_min_shapes(profile_shape(1, &config))
+                            .with_profile_opt_shapes(profile_shape(2, &config))
+                            .with_profile_max_shapes(profile_shape(5, &config))
                             .with_device_id(config.device.parse().unwrap_or(0))
                             .build();
                         eps.push((ep, Ep::TensorRT));
@@ -113,10 +109,29 @@ pub fn detect_worker(
 
         ep_dict.save().unwrap();
 
-        process_frames(array_q_recv, export_q_s, &session, &config).unwrap();
+        let mut backend = OrtBackend::new(session, &config).unwrap();
+        process_frames(array_q_recv, export_q_s, &mut backend, &config).unwrap();
     })
 }
 
+/// Detect worker running a backend that needs no setup on its thread, like `MockBackend`
+pub fn backend_worker<B: InferenceBackend + Send + 'static>(
+    mut backend: B,
+    config: Arc<DetectConfig>,
+    array_q_recv: Receiver<ArrayItem>,
+    export_q_s: Sender<ExportFrame>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        process_frames(array_q_recv, export_q_s, &mut backend, &config).unwrap();
+    })
+}
+
+/// TensorRT optimization profile shape of the model input
+fn profile_shape(batch: usize, config: &DetectConfig) -> String {
+    let shape = config.input.shape(batch, config.target_size);
+    format!("images:{}", itertools::join(shape, "x"))
+}
+
 pub fn load_model(model_path: &Path, ep: ExecutionProviderDispatch) -> Result<Session> {
     let model = Session::builder()?
         .with_execution_providers([ep])?
@@ -133,11 +148,11 @@ fn update_ep_dict(ep_dict: &mut EpDict, ep: Ep, available: bool) {
     }
 }
 
-#[instrument]
-pub fn process_frames(
+#[instrument(skip(backend))]
+pub fn process_frames<B: InferenceBackend>(
     rx: Receiver<ArrayItem>,
     s: Sender<ExportFrame>,
-    model: &Session,
+    backend: &mut B,
     config: &DetectConfig,
 ) -> Result<()> {
     let mut frames: Vec<Frame> = Vec::new();
@@ -148,13 +163,13 @@ pub fn process_frames(
             if !frames.is_empty() {
                 // Process the batch of frames
                 debug!("Processing frame number: {}", frames.len());
-                process_batch(&frames, model, config, &s)?;
+                process_batch(&frames, backend, config, &s)?;
                 frames.clear();
             }
             last_receive_time = Instant::now();
         }
 
-        match rx.recv_timeout(timeout - last_receive_time.elapsed()) {
+        match rx.recv_timeout(timeout.saturating_sub(last_receive_time.elapsed())) {
             Ok(item) => {
                 match item {
                     ArrayItem::Frame(frame_data) => {
@@ -162,13 +177,9 @@ pub fn process_frames(
                     }
                     ArrayItem::ErrFile(err_file) => s
                         .send(ExportFrame {
-                            file: err_file.file,
-                            shoot_time: None,
-                            frame_index: 0,
-                            total_frames: 1,
-                            bboxes: Some(vec![]),
-                            label: None,
-                            error: Some(err_file.error.to_string()),
+                            frame_index: err_file.frame_index,
+                            total_frames: err_file.total_frames,
+                            ..ExportFrame::from_error(err_file.file, err_file.error.to_string())
                         })
                         .unwrap(),
                 }
@@ -182,7 +193,7 @@ pub fn process_frames(
                         "Recieve frame timeout! Processing frame number: {}",
                         frames.len()
                     );
-                    process_batch(&frames, model, config, &s)?;
+                    process_batch(&frames, backend, config, &s)?;
                     frames.clear();
                 }
                 last_receive_time = Instant::now();
@@ -193,7 +204,7 @@ pub fn process_frames(
                         "Channel disconnected! Processing frame number: {}",
                         frames.len()
                     );
-                    process_batch(&frames, model, config, &s)?;
+                    process_batch(&frames, backend, config, &s)?;
                     frames.clear();
                 }
                 // Channel disconnected, exit the loop
@@ -204,79 +215,170 @@ pub fn process_frames(
     Ok(())
 }
 
-pub fn process_batch(
+pub fn process_batch<B: InferenceBackend>(
     frames: &[Frame],
-    model: &Session,
+    backend: &mut B,
     config: &DetectConfig,
     export_q_s: &Sender<ExportFrame>,
 ) -> Result<()> {
-    let batch_size = frames.len();
-    let mut inputs = Array4::<f32>::zeros((batch_size, 3, config.target_size, config.target_size));
-    for (i, frame) in frames.iter().enumerate() {
-        inputs
-            .slice_mut(s![i, .., ..config.target_size, ..config.target_size])
-            .assign(&frame.data);
-    }
-    let outputs: SessionOutputs = model
-        .run(inputs!["images" => inputs.view()].unwrap())
-        .unwrap();
-    let output = outputs["output0"]
-        .try_extract_tensor::<f32>()
-        .unwrap()
-        .t()
-        .into_owned(); //[6, 102000, batch]
+    let batch_boxes = backend.detect(frames, config.conf_thres)?;
 
     // Iterate batch/frame
-    for i in 0..batch_size {
-        let output = output.slice(s![.., .., i]); //[6, 102000]
-        let mut boxes: Vec<Bbox> = vec![];
-        // Iterate bboxes
-        for row in output.axis_iter(Axis(1)) {
-            let row: Vec<_> = row.iter().copied().collect();
-            let class_id = row[5] as usize;
-            let prob = row[4];
-            if prob < config.conf_thres {
-                continue;
-            }
-            let mut x1 = row[0] as f32 * frames[i].ratio - frames[i].padding.0 as f32;
-            let mut y1 = row[1] as f32 * frames[i].ratio - frames[i].padding.1 as f32;
-            let mut x2 = row[2] as f32 * frames[i].ratio - frames[i].padding.0 as f32;
-            let mut y2 = row[3] as f32 * frames[i].ratio - frames[i].padding.1 as f32;
-            x1 = x1.max(0.0).min(frames[i].width as f32);
-            y1 = y1.max(0.0).min(frames[i].height as f32);
-            x2 = x2.max(0.0).min(frames[i].width as f32);
-            y2 = y2.max(0.0).min(frames[i].height as f32);
-            let bbox = Bbox {
-                class: class_id,
-                score: prob,
-                x1,
-                y1,
-                x2,
-                y2,
-            };
-            boxes.push(bbox);
+    for (frame, mut boxes) in frames.iter().zip(batch_boxes) {
+        let mut nms_boxes = nms(&mut boxes, true, 100, config.iou_thres);
+        if config.box_orientation == BoxOrientation::Raw {
+            nms_boxes = nms_boxes
+                .iter()
+                .map(|bbox| {
+                    bbox.to_raw_orientation(
+                        frame.orientation,
+                        frame.width as f32,
+                        frame.height as f32,
+                    )
+                })
+                .collect();
         }
-        let nms_boxes = nms(&mut boxes, true, 100, config.iou_thres);
 
         let label = get_label(&nms_boxes, &config.class_map);
 
-        let shoot_time = match frames[i].shoot_time {
-            Some(shoot_time) => Some(shoot_time.to_string()),
-            None => None,
+        let export_frame = ExportFrame::from_frame(frame, nms_boxes, label);
+        export_q_s.send(export_frame).unwrap();
+    }
+    Ok(())
+}
+
+/// Boxes of one frame above `conf_thres`, read from its `[N, 6]` rows of
+/// `x1, y1, x2, y2, score, class` in model input pixels and mapped back to the image.
+/// The score is checked first so rows below the threshold are skipped without reading the rest.
+pub fn frame_boxes(output: ArrayView2<f32>, frame: &Frame, conf_thres: f32) -> Vec<Bbox> {
+    let mut boxes = Vec::new();
+    for row in output.outer_iter() {
+        let score = row[4];
+        if score < conf_thres {
+            continue;
+        }
+        let (x1, y1) = frame.letterbox.input_to_image(row[0], row[1]);
+        let (x2, y2) = frame.letterbox.input_to_image(row[2], row[3]);
+        boxes.push(Bbox {
+            class: row[5] as usize,
+            score,
+            x1,
+            y1,
+            x2,
+            y2,
+        });
+    }
+    boxes
+}
+
+/// Model input tensor of a detect worker, allocated once for a full batch and refilled in place
+pub struct InputBuffer {
+    input: InputTensor,
+    imgsz: usize,
+    batch_size: usize,
+    tensor: BufferTensor,
+}
+
+enum BufferTensor {
+    Float32(Tensor<f32>),
+    Float16(Tensor<f16>),
+    Uint8(Tensor<u8>),
+}
+
+impl InputBuffer {
+    pub fn new(input: InputTensor, batch_size: usize, imgsz: usize) -> Result<Self> {
+        let shape = input.shape(batch_size, imgsz);
+        let tensor = match input.dtype {
+            InputDtype::Float32 => {
+                BufferTensor::Float32(Tensor::from_array(Array4::default(shape))?)
+            }
+            InputDtype::Float16 => {
+                BufferTensor::Float16(Tensor::from_array(Array4::default(shape))?)
+            }
+            InputDtype::Uint8 => BufferTensor::Uint8(Tensor::from_array(Array4::default(shape))?),
         };
+        Ok(InputBuffer {
+            input,
+            imgsz,
+            batch_size,
+            tensor,
+        })
+    }
 
-        let export_frame = ExportFrame {
-            file: frames[i].file.clone(),
-            shoot_time: shoot_time,
-            frame_index: frames[i].frame_index,
-            total_frames: frames[i].total_frames,
-            bboxes: Some(nms_boxes),
-            label: Some(label),
-            error: None,
+    /// Fill the buffer with `frames` and return it as the model input.
+    /// Partial batches, only sent on timeout or at the end, get a tensor of their own size.
+    pub fn fill(&mut self, frames: &[Frame]) -> Result<SessionInputValue<'_>> {
+        let input = &self.input;
+        let scale = input.scale();
+        if frames.len() != self.batch_size {
+            let value = match input.dtype {
+                InputDtype::Float32 => {
+                    Tensor::from_array(batch_array(frames, input, self.imgsz, |x| {
+                        x as f32 * scale
+                    }))?
+                    .into_dyn()
+                }
+                InputDtype::Float16 => {
+                    Tensor::from_array(batch_array(frames, input, self.imgsz, |x| {
+                        f16::from_f32(x as f32 * scale)
+                    }))?
+                    .into_dyn()
+                }
+                InputDtype::Uint8 => {
+                    Tensor::from_array(batch_array(frames, input, self.imgsz, |x| x))?.into_dyn()
+                }
+            };
+            return Ok(value.into());
+        }
+        let value = match &mut self.tensor {
+            BufferTensor::Float32(tensor) => {
+                let batch = tensor.extract_tensor_mut().into_dimensionality::<Ix4>()?;
+                fill_batch(batch, frames, input.layout, |x| x as f32 * scale);
+                tensor.view().into()
+            }
+            BufferTensor::Float16(tensor) => {
+                let batch = tensor.extract_tensor_mut().into_dimensionality::<Ix4>()?;
+                fill_batch(batch, frames, input.layout, |x| {
+                    f16::from_f32(x as f32 * scale)
+                });
+                tensor.view().into()
+            }
+            BufferTensor::Uint8(tensor) => {
+                let batch = tensor.extract_tensor_mut().into_dimensionality::<Ix4>()?;
+                fill_batch(batch, frames, input.layout, |x| x);
+                tensor.view().into()
+            }
         };
-        export_q_s.send(export_frame).unwrap();
+        Ok(value)
+    }
+}
+
+/// Convert the `u8` frames to a new model input array
+pub fn batch_array<T: Clone + Default>(
+    frames: &[Frame],
+    input: &InputTensor,
+    imgsz: usize,
+    convert: impl Fn(u8) -> T,
+) -> Array4<T> {
+    let mut batch = Array4::<T>::default(input.shape(frames.len(), imgsz));
+    fill_batch(batch.view_mut(), frames, input.layout, convert);
+    batch
+}
+
+/// Write the `u8` frames into an existing model input array of the same batch size
+pub fn fill_batch<T>(
+    mut batch: ArrayViewMut4<T>,
+    frames: &[Frame],
+    layout: InputLayout,
+    convert: impl Fn(u8) -> T,
+) {
+    for (mut item, frame) in batch.outer_iter_mut().zip(frames) {
+        let data = match layout {
+            InputLayout::Nchw => frame.data.view().permuted_axes([2, 0, 1]),
+            InputLayout::Nhwc => frame.data.view(),
+        };
+        item.zip_mut_with(&data, |dst, &src| *dst = convert(src));
     }
-    Ok(())
 }
 
 fn get_label(bboxes: &Vec<Bbox>, cls_map: &HashMap<usize, String>) -> HashSet<String> {
@@ -291,10 +393,173 @@ fn get_label(bboxes: &Vec<Bbox>, cls_map: &HashMap<usize, String>) -> HashSet<St
 
         let label = match cls_map.get(&class_id) {
             Some(label) => label.to_string(),
-            None => Err(anyhow!("Class ID not found")).unwrap(),
+            None => panic!("Class ID not found"),
         };
 
         labels.insert(label);
     }
     labels
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::letterbox::Letterbox;
+    use crate::stamp::StampMask;
+    use crate::utils::{FileItem, PadAlign};
+    use image::metadata::Orientation;
+    use ndarray::Array3;
+
+    fn test_frame(data: Array3<u8>, width: usize, height: usize, imgsz: u32) -> Frame {
+        Frame {
+            file: FileItem::new(0, 0, PathBuf::from("image.jpg"), None),
+            data,
+            width,
+            height,
+            letterbox: Letterbox::new(
+                width as u32,
+                height as u32,
+                &StampMask::default(),
+                imgsz,
+                PadAlign::Center,
+            ),
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: None,
+            shoot_time: None,
+            time_source: None,
+            orientation: Orientation::NoTransforms,
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_batch_array() {
+        // 2x2 image with a red, a blue and two black pixels
+        let pixels = vec![255, 0, 0, 0, 0, 255, 0, 0, 0, 0, 0, 0];
+        let data = Array3::from_shape_vec((2, 2, 3), pixels.clone()).unwrap();
+        let frame = test_frame(data, 2, 2, 2);
+        let frames = [frame];
+
+        let nchw = InputTensor::default();
+        let batch = batch_array(&frames, &nchw, 2, |x| x as f32 / 255.0);
+        assert_eq!(batch.shape(), &[1, 3, 2, 2]);
+        assert_eq!(batch[[0, 0, 0, 0]], 1.0);
+        assert_eq!(batch[[0, 2, 0, 1]], 1.0);
+        assert_eq!(batch[[0, 1, 0, 0]], 0.0);
+
+        let nhwc = InputTensor {
+            dtype: InputDtype::Uint8,
+            layout: InputLayout::Nhwc,
+            normalize: false,
+        };
+        let batch = batch_array(&frames, &nhwc, 2, |x| x);
+        assert_eq!(batch.shape(), &[1, 2, 2, 3]);
+        assert_eq!(batch.as_slice().unwrap(), pixels.as_slice());
+    }
+
+    #[test]
+    fn test_fill_batch_reuse() {
+        let frames = [
+            test_frame(Array3::from_elem((2, 2, 3), 255), 2, 2, 2),
+            test_frame(Array3::zeros((2, 2, 3)), 2, 2, 2),
+        ];
+        let mut batch = Array4::from_elem((2, 3, 2, 2), 0.5f32);
+        fill_batch(batch.view_mut(), &frames, InputLayout::Nchw, |x| {
+            x as f32 / 255.0
+        });
+        assert!(batch.outer_iter().next().unwrap().iter().all(|&x| x == 1.0));
+        assert!(batch.outer_iter().nth(1).unwrap().iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn test_frame_boxes() {
+        // 200x100 image letterboxed into 100x100 at (0, 25)
+        let frame = test_frame(Array3::zeros((100, 100, 3)), 200, 100, 100);
+        let output = ndarray::arr2(&[
+            [10.0, 25.0, 60.0, 75.0, 0.9, 1.0],
+            [0.0, 0.0, 100.0, 100.0, 0.1, 0.0],
+            [50.0, 50.0, 100.0, 75.0, 0.5, 2.0],
+        ]);
+        let boxes = frame_boxes(output.view(), &frame, 0.2);
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(boxes[0].class, 1);
+        assert_eq!(boxes[0].score, 0.9);
+        assert_eq!((boxes[0].x1, boxes[0].y1), (20.0, 0.0));
+        assert_eq!((boxes[0].x2, boxes[0].y2), (120.0, 100.0));
+        assert_eq!(boxes[1].class, 2);
+        assert_eq!((boxes[1].x1, boxes[1].y1), (100.0, 50.0));
+    }
+
+    #[test]
+    fn test_process_frames_mock() {
+        let mock: MockBackend = serde_json::from_str(
+            r#"{
+                "default": [],
+                "files": {
+                    "deer.jpg": [
+                        {"x1": 10, "y1": 10, "x2": 50, "y2": 50, "score": 0.9, "class": 0},
+                        {"x1": 12, "y1": 10, "x2": 50, "y2": 50, "score": 0.8, "class": 0},
+                        {"x1": 60, "y1": 60, "x2": 90, "y2": 90, "score": 0.1, "class": 1}
+                    ]
+                }
+            }"#,
+        )
+        .unwrap();
+        let config = DetectConfig {
+            device: "cpu".to_string(),
+            model_path: PathBuf::from("mock.onnx"),
+            target_size: 100,
+            input: InputTensor::default(),
+            class_map: HashMap::from([(0, "Animal".to_string()), (1, "Person".to_string())]),
+            conf_thres: 0.2,
+            iou_thres: 0.45,
+            box_orientation: BoxOrientation::Display,
+            batch_size: 2,
+            timeout: 10,
+        };
+        let (array_q_s, array_q_r) = crossbeam_channel::unbounded();
+        let (export_q_s, export_q_r) = crossbeam_channel::unbounded();
+        let mut deer = test_frame(Array3::zeros((100, 100, 3)), 100, 100, 100);
+        deer.file = FileItem::new(0, 0, PathBuf::from("deer.jpg"), None);
+        let blank = test_frame(Array3::zeros((100, 100, 3)), 100, 100, 100);
+        array_q_s.send(ArrayItem::Frame(deer)).unwrap();
+        array_q_s.send(ArrayItem::Frame(blank)).unwrap();
+        array_q_s
+            .send(ArrayItem::ErrFile(crate::media::ErrFile::new(
+                FileItem::new(0, 0, PathBuf::from("broken.jpg"), None),
+                anyhow::anyhow!("Failed to decode"),
+            )))
+            .unwrap();
+        array_q_s
+            .send(ArrayItem::ErrFile(crate::media::ErrFile {
+                file: FileItem::new(0, 0, PathBuf::from("short.mp4"), None),
+                error: anyhow::anyhow!("Failed to decode frame 6"),
+                frame_index: 6,
+                total_frames: 3,
+            }))
+            .unwrap();
+        drop(array_q_s);
+
+        let handle = backend_worker(mock, Arc::new(config), array_q_r, export_q_s);
+        handle.join().unwrap();
+        let exported: HashMap<PathBuf, ExportFrame> = export_q_r
+            .iter()
+            .map(|frame| (frame.file.file_path.clone(), frame))
+            .collect();
+        assert_eq!(exported.len(), 4);
+
+        let deer = &exported[&PathBuf::from("deer.jpg")];
+        // The overlapping box is removed by NMS and the low score one by the threshold
+        assert_eq!(deer.bboxes.as_ref().unwrap().len(), 1);
+        assert_eq!(deer.label, Some(HashSet::from(["Animal".to_string()])));
+        let blank = &exported[&PathBuf::from("image.jpg")];
+        assert_eq!(blank.label, Some(HashSet::from(["Blank".to_string()])));
+        let broken = &exported[&PathBuf::from("broken.jpg")];
+        assert_eq!(broken.error.as_deref(), Some("Failed to decode"));
+        assert_eq!((broken.frame_index, broken.total_frames), (0, 1));
+        let short = &exported[&PathBuf::from("short.mp4")];
+        assert_eq!((short.frame_index, short.total_frames), (6, 3));
+    }
+}
@@ -1,15 +1,18 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf, Prefix};
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
-use csv::WriterBuilder;
-use serde::{Deserialize, Serialize};
-use tracing::info;
+use anyhow::{Context, Result};
+use csv::{StringRecord, WriterBuilder};
+use serde::{Deserialize, Serialize, Serializer};
+use tracing::{error, info, warn};
 
-use crate::utils::{Bbox, FileItem};
+use crate::media::{Frame, TimeSource};
+use crate::metadata::MediaMetadata;
+use crate::utils::{relative_path, Bbox, FileIdentity, FileItem, FileKey, ModelConfig};
 use crate::ExportFormat;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,41 +20,447 @@ pub struct ExportFrame {
     #[serde(flatten)]
     pub file: FileItem,
     pub shoot_time: Option<String>,
+    #[serde(default)]
+    pub time_source: Option<TimeSource>,
     pub frame_index: usize,
     pub total_frames: usize,
+    #[serde(default)]
+    pub frame_time: Option<f32>,
+    #[serde(default)]
+    pub frame_shoot_time: Option<String>,
     pub bboxes: Option<Vec<Bbox>>,
     pub label: Option<HashSet<String>>,
     pub error: Option<String>,
+    #[serde(flatten)]
+    pub metadata: MediaMetadata,
+    /// Path of the file with the same content whose detections were copied to this one
+    #[serde(default)]
+    pub duplicate_of: Option<PathBuf>,
+    /// Difference hash of the letterboxed image as 16 hex digits
+    #[serde(default)]
+    pub perceptual_hash: Option<String>,
+    /// Path of the earlier image of the same burst this one is nearly identical to
+    #[serde(default)]
+    pub near_duplicate_of: Option<PathBuf>,
+}
+
+impl ExportFrame {
+    /// Record of a file that failed or was skipped, so it still shows up in the export
+    pub fn from_error(file: FileItem, error: String) -> Self {
+        ExportFrame {
+            file,
+            shoot_time: None,
+            time_source: None,
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: None,
+            frame_shoot_time: None,
+            bboxes: Some(vec![]),
+            label: None,
+            error: Some(error),
+            metadata: MediaMetadata::default(),
+            duplicate_of: None,
+            perceptual_hash: None,
+            near_duplicate_of: None,
+        }
+    }
+
+    /// Record of a decoded frame with its detections
+    pub fn from_frame(frame: &Frame, bboxes: Vec<Bbox>, label: HashSet<String>) -> Self {
+        ExportFrame {
+            file: frame.file.clone(),
+            shoot_time: frame.shoot_time.map(|shoot_time| shoot_time.to_string()),
+            time_source: frame.time_source,
+            frame_index: frame.frame_index,
+            total_frames: frame.total_frames,
+            frame_time: frame.frame_time,
+            frame_shoot_time: frame
+                .frame_shoot_time()
+                .map(|frame_shoot_time| frame_shoot_time.to_string()),
+            bboxes: Some(bboxes),
+            label: Some(label),
+            error: None,
+            metadata: (*frame.metadata).clone(),
+            duplicate_of: None,
+            perceptual_hash: None,
+            near_duplicate_of: None,
+        }
+    }
+
+    /// Record of a single detected image with `labels` and no boxes
+    #[cfg(test)]
+    pub(crate) fn detected(file: FileItem, labels: &[&str]) -> Self {
+        ExportFrame {
+            file,
+            shoot_time: None,
+            time_source: None,
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: None,
+            frame_shoot_time: None,
+            bboxes: Some(vec![]),
+            label: Some(labels.iter().map(|label| label.to_string()).collect()),
+            error: None,
+            metadata: MediaMetadata::default(),
+            duplicate_of: None,
+            perceptual_hash: None,
+            near_duplicate_of: None,
+        }
+    }
+
+    /// Frame as exported with `--relative-paths`: paths under the file's root relative to it,
+    /// with `/` separators. Duplicates under another root keep their absolute path.
+    pub fn relative_to_root(&self) -> Cow<'_, ExportFrame> {
+        let root = &self.file.root;
+        if root.as_os_str().is_empty() {
+            return Cow::Borrowed(self);
+        }
+        let relative = |path: &Path| {
+            if path.starts_with(root) {
+                PathBuf::from(relative_path(root, path))
+            } else {
+                path.to_path_buf()
+            }
+        };
+        let mut frame = self.clone();
+        frame.file.file_path = relative(&self.file.file_path);
+        frame.duplicate_of = self.duplicate_of.as_deref().map(relative);
+        frame.near_duplicate_of = self.near_duplicate_of.as_deref().map(relative);
+        Cow::Owned(frame)
+    }
+
+    /// Join paths exported relative to the file's root back onto it
+    pub fn resolve_from_root(&mut self) {
+        let root = &self.file.root;
+        if root.as_os_str().is_empty() {
+            return;
+        }
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = root.join(&path);
+            }
+        };
+        resolve(&mut self.file.file_path);
+        if let Some(path) = &mut self.duplicate_of {
+            resolve(path);
+        }
+        if let Some(path) = &mut self.near_duplicate_of {
+            resolve(path);
+        }
+    }
+}
+
+/// Run settings saved next to the export, checked on resume so a checkpoint is only
+/// continued with the model, thresholds and input folder it was written with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    pub version: String,
+    pub model: String,
+    pub model_path: PathBuf,
+    pub imgsz: usize,
+    pub conf_thres: f32,
+    pub iou_thres: f32,
+    /// Processed folders. Headers written before multiple folders were supported have one `root`
+    #[serde(alias = "root", deserialize_with = "one_or_many")]
+    pub roots: Vec<PathBuf>,
+}
+
+fn one_or_many<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<PathBuf>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(root) => vec![root],
+        OneOrMany::Many(roots) => roots,
+    })
+}
+
+impl CheckpointHeader {
+    pub fn new(model: &ModelConfig, conf_thres: f32, iou_thres: f32, roots: &[PathBuf]) -> Self {
+        CheckpointHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            model: model.name.clone(),
+            model_path: model.path.clone(),
+            imgsz: model.imgsz,
+            conf_thres,
+            iou_thres,
+            roots: roots.to_vec(),
+        }
+    }
+
+    /// Settings of `self` that differ from `other`, as `name: self != other`.
+    /// The md5rs version is informational only.
+    pub fn differences(&self, other: &CheckpointHeader) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut compare = |name: &str, a: String, b: String| {
+            if a != b {
+                differences.push(format!("{}: {} != {}", name, a, b));
+            }
+        };
+        compare("model", self.model.clone(), other.model.clone());
+        compare(
+            "model_path",
+            self.model_path.display().to_string(),
+            other.model_path.display().to_string(),
+        );
+        compare("imgsz", self.imgsz.to_string(), other.imgsz.to_string());
+        compare(
+            "conf_thres",
+            self.conf_thres.to_string(),
+            other.conf_thres.to_string(),
+        );
+        compare(
+            "iou_thres",
+            self.iou_thres.to_string(),
+            other.iou_thres.to_string(),
+        );
+        let roots = |roots: &[PathBuf]| {
+            roots
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        compare("roots", roots(&self.roots), roots(&other.roots));
+        differences
+    }
+}
+
+/// Values of the placeholders of an `--output` template
+pub struct OutputNames {
+    /// `{folder}`, name of the first processed folder
+    pub folder: String,
+    /// `{model}`, name of the model in its toml
+    pub model: String,
+    /// `{timestamp}`, local start time of the run like `20240501-063000`
+    pub timestamp: String,
+}
+
+/// `{folder}` of `root`: its name, the drive letter of a drive root like `E`, or `root`
+pub fn folder_name(root: &Path) -> String {
+    if let Some(name) = root.file_name() {
+        return name.to_string_lossy().into_owned();
+    }
+    match root.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => (letter as char).to_string(),
+            _ => "root".to_string(),
+        },
+        _ => "root".to_string(),
+    }
+}
+
+/// Export file of the `--output` template. Placeholders are replaced by `names`, a template
+/// ending with a separator or naming an existing folder gets `result.json`/`result.csv` in it,
+/// and the extension of `format` is added unless the template has it.
+pub fn output_path(template: &str, format: &ExportFormat, names: &OutputNames) -> Result<PathBuf> {
+    let extension = match format {
+        ExportFormat::Json => "json",
+        ExportFormat::Csv => "csv",
+    };
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed placeholder in output {}", template))?;
+        expanded.push_str(match &rest[start + 1..start + end] {
+            "folder" => &names.folder,
+            "model" => &names.model,
+            "timestamp" => &names.timestamp,
+            other => anyhow::bail!("Unknown placeholder {{{}}} in output {}", other, template),
+        });
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+
+    let path = PathBuf::from(&expanded);
+    if expanded.ends_with(['/', std::path::MAIN_SEPARATOR]) || path.is_dir() {
+        return Ok(path.join(format!("result.{}", extension)));
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext == extension => Ok(path),
+        Some(ext @ ("json" | "csv")) => Err(anyhow::anyhow!(
+            "Output {} is a {} file but the export format is {}",
+            expanded,
+            ext,
+            extension
+        )),
+        _ => Ok(PathBuf::from(format!("{}.{}", expanded, extension))),
+    }
+}
+
+/// Header of the export at `result_path`, a hidden file next to it so indexing skips it
+pub fn header_path(result_path: &Path) -> PathBuf {
+    let name = result_path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    result_path.with_file_name(format!(".{}.header.json", name))
+}
+
+pub fn write_header(result_path: &Path, header: &CheckpointHeader) -> Result<()> {
+    write_atomic(&header_path(result_path), |writer| {
+        serde_json::to_writer_pretty(writer, header)?;
+        Ok(())
+    })
+}
+
+/// Header of the export at `result_path`, or `None` for exports written before headers existed
+pub fn read_header(result_path: &Path) -> Result<Option<CheckpointHeader>> {
+    let path = header_path(result_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = std::fs::read_to_string(&path)?;
+    let header = serde_json::from_str(&json)
+        .with_context(|| format!("Invalid checkpoint header {}", path.display()))?;
+    Ok(Some(header))
+}
+
+/// Move the export at `result_path` and its header aside to `<name>.previous.<ext>`, replacing
+/// an earlier one. Returns where the export was moved.
+pub fn move_previous_export(result_path: &Path) -> Result<PathBuf> {
+    let extension = result_path
+        .extension()
+        .map(|ext| ext.to_string_lossy())
+        .unwrap_or_default();
+    let previous = result_path.with_extension(format!("previous.{}", extension));
+    std::fs::rename(result_path, &previous)?;
+    let header = header_path(result_path);
+    if header.exists() {
+        std::fs::rename(&header, header_path(&previous))?;
+    }
+    Ok(previous)
+}
+
+/// Write `path` through a hidden temporary file in the same folder, synced and renamed over it,
+/// so a crash while writing leaves either the previous or the new file, never a truncated one
+fn write_atomic<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<()>,
+{
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.tmp", name));
+    if let Err(e) = write_synced(&tmp_path, write) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    std::fs::rename(&tmp_path, path)?;
+    // Persist the rename itself, directories can't be opened for syncing on Windows. A bare
+    // file name has an empty parent, the working folder
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Write `path` and sync it to disk
+fn write_synced<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<&File>) -> Result<()>,
+{
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+    Ok(())
+}
+
+fn csv_field<'a>(record: &'a StringRecord, headers: &StringRecord, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .position(|h| h == name)
+        .and_then(|i| record.get(i))
 }
 
 pub fn parse_export_csv<P: AsRef<Path>>(csv: P) -> Result<Vec<ExportFrame>> {
     let file = File::open(csv)?;
     let mut rdr = csv::Reader::from_reader(file);
+    let headers = rdr.headers()?.clone();
+    let field = |record: &StringRecord, name: &str| -> Result<String> {
+        csv_field(record, &headers, name)
+            .map(|s| s.to_string())
+            .with_context(|| format!("Column {} not found", name))
+    };
+    let optional = |record: &StringRecord, name: &str| -> Option<String> {
+        csv_field(record, &headers, name)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    };
     let mut export_data = Vec::new();
     for frame in rdr.records() {
         let frame = frame?;
         let file_item = FileItem {
-            folder_id: frame[0].parse::<_>()?,
-            file_id: frame[1].parse::<_>()?,
-            file_path: frame[2].parse()?,
-            tmp_path: frame[2].parse()?,
+            folder_id: field(&frame, "folder_id")?.parse::<_>()?,
+            file_id: field(&frame, "file_id")?.parse::<_>()?,
+            file_path: field(&frame, "file_path")?.parse()?,
+            tmp_path: field(&frame, "file_path")?.parse()?,
+            root: optional(&frame, "root")
+                .map(PathBuf::from)
+                .unwrap_or_default(),
+            identity: FileIdentity {
+                relative_path: optional(&frame, "relative_path").unwrap_or_default(),
+                size: optional(&frame, "size")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_default(),
+                mtime: optional(&frame, "mtime").and_then(|s| s.parse().ok()),
+            },
+            hash: optional(&frame, "hash"),
         };
-        let bboxes = frame[7].to_string().replace("\"\"", "\"");
+        let bboxes = field(&frame, "bboxes")?.replace("\"\"", "\"");
         let bboxes = serde_json::from_str(&bboxes)?;
         let frame_item = ExportFrame {
             file: file_item,
-            shoot_time: Some(frame[3].to_string()),
-            frame_index: frame[4].parse::<_>()?,
-            total_frames: frame[5].parse::<_>()?,
+            shoot_time: Some(field(&frame, "shoot_time")?),
+            time_source: csv_field(&frame, &headers, "time_source").and_then(|s| s.parse().ok()),
+            frame_index: field(&frame, "frame_index")?.parse::<_>()?,
+            total_frames: field(&frame, "total_frames")?.parse::<_>()?,
+            frame_time: csv_field(&frame, &headers, "frame_time").and_then(|s| s.parse().ok()),
+            frame_shoot_time: csv_field(&frame, &headers, "frame_shoot_time")
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string()),
             bboxes,
             label: Some(
-                frame[8]
-                    .to_string()
+                field(&frame, "label")?
                     .split(";")
                     .map(|s| s.to_string())
                     .collect(),
             ),
-            error: Some(frame[9].to_string()),
+            error: Some(field(&frame, "error")?),
+            metadata: MediaMetadata {
+                make: optional(&frame, "make"),
+                model: optional(&frame, "model"),
+                serial_number: optional(&frame, "serial_number"),
+                latitude: optional(&frame, "latitude").and_then(|s| s.parse().ok()),
+                longitude: optional(&frame, "longitude").and_then(|s| s.parse().ok()),
+                altitude: optional(&frame, "altitude").and_then(|s| s.parse().ok()),
+                temperature: optional(&frame, "temperature").and_then(|s| s.parse().ok()),
+                moon_phase: optional(&frame, "moon_phase"),
+                trigger_mode: optional(&frame, "trigger_mode"),
+                sequence_number: optional(&frame, "sequence_number").and_then(|s| s.parse().ok()),
+                sequence_length: optional(&frame, "sequence_length").and_then(|s| s.parse().ok()),
+            },
+            duplicate_of: optional(&frame, "duplicate_of").map(PathBuf::from),
+            perceptual_hash: optional(&frame, "perceptual_hash"),
+            near_duplicate_of: optional(&frame, "near_duplicate_of").map(PathBuf::from),
         };
         export_data.push(frame_item);
     }
@@ -62,118 +471,897 @@ pub fn export_worker(
     checkpoint: usize,
     checkpoint_counter: &Arc<Mutex<usize>>,
     format: &ExportFormat,
-    folder_path: &PathBuf,
+    result_path: &Path,
+    relative_paths: bool,
     export_q_r: crossbeam_channel::Receiver<ExportFrame>,
     export_data: &Arc<Mutex<Vec<ExportFrame>>>,
 ) {
-    loop {
-        match export_q_r.recv() {
-            Ok(export_frame) => {
-                let mut checkpoint_counter = checkpoint_counter.lock().unwrap();
-                if *checkpoint_counter % checkpoint == 0 && *checkpoint_counter != 0 {
-                    let export_data = export_data.lock().unwrap();
-                    info!("Exported {} frames", export_data.len());
-                    match format {
-                        ExportFormat::Json => write_json(&export_data, folder_path).unwrap(),
-                        ExportFormat::Csv => write_csv(&export_data, folder_path).unwrap(),
-                    }
+    while let Ok(export_frame) = export_q_r.recv() {
+        let mut checkpoint_counter = checkpoint_counter.lock().unwrap();
+        if checkpoint_counter.is_multiple_of(checkpoint) && *checkpoint_counter != 0 {
+            let export_data = export_data.lock().unwrap();
+            info!("Exported {} frames", export_data.len());
+            match format {
+                ExportFormat::Json => {
+                    write_json(&export_data, result_path, relative_paths).unwrap()
                 }
-                export_data.lock().unwrap().push(export_frame);
-                *checkpoint_counter += 1;
+                ExportFormat::Csv => write_csv(&export_data, result_path, relative_paths).unwrap(),
             }
-            Err(_) => break,
         }
+        export_data.lock().unwrap().push(export_frame);
+        *checkpoint_counter += 1;
     }
 }
 
-fn write_json(export_data: &Vec<ExportFrame>, folder_path: &PathBuf) -> Result<()> {
-    let json = serde_json::to_string_pretty(export_data)?;
-    let json_path = folder_path.join("result.json");
-    let mut file = File::create(json_path)?;
-    file.write_all(json.as_bytes())?;
-    Ok(())
+/// Frames as written, with paths relative to their root with `relative_paths`
+fn exported_frames(
+    export_data: &[ExportFrame],
+    relative_paths: bool,
+) -> impl Iterator<Item = Cow<'_, ExportFrame>> {
+    export_data.iter().map(move |frame| {
+        if relative_paths {
+            frame.relative_to_root()
+        } else {
+            Cow::Borrowed(frame)
+        }
+    })
 }
 
-fn write_csv(export_data: &Vec<ExportFrame>, folder_path: &PathBuf) -> Result<()> {
-    let csv_path = folder_path.join("result.csv");
-    let mut wtr = WriterBuilder::new()
-        .has_headers(false)
-        .from_path(csv_path)?;
+fn write_json(export_data: &[ExportFrame], json_path: &Path, relative_paths: bool) -> Result<()> {
+    write_atomic(json_path, |writer| {
+        let mut serializer = serde_json::Serializer::pretty(writer);
+        serializer.collect_seq(exported_frames(export_data, relative_paths))?;
+        Ok(())
+    })
+}
+
+fn write_csv(export_data: &[ExportFrame], csv_path: &Path, relative_paths: bool) -> Result<()> {
+    write_atomic(csv_path, |writer| {
+        write_csv_records(export_data, relative_paths, writer)
+    })
+}
+
+fn write_csv_records<W: Write>(
+    export_data: &[ExportFrame],
+    relative_paths: bool,
+    writer: W,
+) -> Result<()> {
+    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(writer);
     wtr.write_record([
         "folder_id",
         "file_id",
         "file_path",
         "shoot_time",
+        "time_source",
         "frame_index",
         "total_frames",
+        "frame_time",
+        "frame_shoot_time",
         "bboxes",
         "label",
         "error",
+        "make",
+        "model",
+        "serial_number",
+        "latitude",
+        "longitude",
+        "altitude",
+        "temperature",
+        "moon_phase",
+        "trigger_mode",
+        "sequence_number",
+        "sequence_length",
+        "root",
+        "relative_path",
+        "size",
+        "mtime",
+        "hash",
+        "duplicate_of",
+        "perceptual_hash",
+        "near_duplicate_of",
     ])?;
-    for export_frame in export_data {
-        wtr.write_record(&[
-            export_frame.file.folder_id.to_string().as_str(),
-            export_frame.file.file_id.to_string().as_str(),
-            export_frame
-                .file
-                .file_path
-                .to_string_lossy()
-                .into_owned()
-                .as_str(),
-            export_frame
-                .shoot_time
-                .clone()
-                .unwrap_or("".to_string())
-                .as_str(),
-            export_frame.frame_index.to_string().as_str(),
-            export_frame.total_frames.to_string().as_str(),
-            serde_json::to_string(&export_frame.bboxes)
-                .unwrap_or("".to_string())
-                .as_str(),
-            &itertools::join(
+    for export_frame in exported_frames(export_data, relative_paths) {
+        wtr.write_record(
+            [
+                export_frame.file.folder_id.to_string().as_str(),
+                export_frame.file.file_id.to_string().as_str(),
+                export_frame
+                    .file
+                    .file_path
+                    .to_string_lossy()
+                    .into_owned()
+                    .as_str(),
                 export_frame
-                    .label
+                    .shoot_time
                     .clone()
-                    .unwrap_or(HashSet::from(["".to_string()])),
-                ";",
-            ),
-            export_frame
-                .error
-                .clone()
-                .unwrap_or("".to_string())
-                .as_str(),
-        ])?;
+                    .unwrap_or("".to_string())
+                    .as_str(),
+                export_frame
+                    .time_source
+                    .map(|s| s.as_str())
+                    .unwrap_or_default(),
+                export_frame.frame_index.to_string().as_str(),
+                export_frame.total_frames.to_string().as_str(),
+                export_frame
+                    .frame_time
+                    .map(|t| t.to_string())
+                    .unwrap_or_default()
+                    .as_str(),
+                export_frame
+                    .frame_shoot_time
+                    .clone()
+                    .unwrap_or_default()
+                    .as_str(),
+                serde_json::to_string(&export_frame.bboxes)
+                    .unwrap_or("".to_string())
+                    .as_str(),
+                &itertools::join(
+                    export_frame
+                        .label
+                        .clone()
+                        .unwrap_or(HashSet::from(["".to_string()])),
+                    ";",
+                ),
+                export_frame
+                    .error
+                    .clone()
+                    .unwrap_or("".to_string())
+                    .as_str(),
+            ]
+            .into_iter()
+            .map(|s| s.to_string())
+            .chain(metadata_fields(&export_frame.metadata))
+            .chain([export_frame.file.root.to_string_lossy().into_owned()])
+            .chain(identity_fields(&export_frame.file.identity))
+            .chain([
+                export_frame.file.hash.clone().unwrap_or_default(),
+                export_frame
+                    .duplicate_of
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                export_frame.perceptual_hash.clone().unwrap_or_default(),
+                export_frame
+                    .near_duplicate_of
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            ]),
+        )?;
     }
     wtr.flush()?;
     Ok(())
 }
 
+fn identity_fields(identity: &FileIdentity) -> [String; 3] {
+    if identity.is_empty() {
+        return Default::default();
+    }
+    [
+        identity.relative_path.clone(),
+        identity.size.to_string(),
+        identity.mtime.map(|t| t.to_string()).unwrap_or_default(),
+    ]
+}
+
+fn metadata_fields(metadata: &MediaMetadata) -> [String; 11] {
+    fn opt<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+    }
+    [
+        opt(&metadata.make),
+        opt(&metadata.model),
+        opt(&metadata.serial_number),
+        opt(&metadata.latitude),
+        opt(&metadata.longitude),
+        opt(&metadata.altitude),
+        opt(&metadata.temperature),
+        opt(&metadata.moon_phase),
+        opt(&metadata.trigger_mode),
+        opt(&metadata.sequence_number),
+        opt(&metadata.sequence_length),
+    ]
+}
+
 pub fn export(
-    folder_path: &PathBuf,
+    result_path: &Path,
     export_data: Arc<Mutex<Vec<ExportFrame>>>,
     export_format: &ExportFormat,
+    relative_paths: bool,
 ) -> Result<()> {
     let export_data = Arc::try_unwrap(export_data).unwrap().into_inner().unwrap();
     info!("Exported {} frames", export_data.len());
     match export_format {
         ExportFormat::Json => {
-            write_json(&export_data, folder_path)?;
+            write_json(&export_data, result_path, relative_paths)?;
         }
         ExportFormat::Csv => {
-            write_csv(&export_data, folder_path)?;
+            write_csv(&export_data, result_path, relative_paths)?;
         }
     }
     Ok(())
 }
 
+/// Drop frames of files replaced since they were exported: same root and relative path as an
+/// indexed file but another content hash if both were hashed, otherwise another size or
+/// modification time. The indexed file is processed again. Frames of unchanged files take the
+/// identity of the indexed file, so files touched without changing their hash are done.
+/// Frames of files no longer indexed are kept.
+fn drop_changed_files(frames: Vec<ExportFrame>, files: &HashSet<FileItem>) -> Vec<ExportFrame> {
+    let current: HashMap<(&Path, &str), &FileItem> = files
+        .iter()
+        .map(|file| {
+            (
+                (file.root.as_path(), file.identity.relative_path.as_str()),
+                file,
+            )
+        })
+        .collect();
+    let mut changed = HashSet::new();
+    let frames: Vec<ExportFrame> = frames
+        .into_iter()
+        .filter_map(|mut frame| {
+            let identity = &frame.file.identity;
+            if identity.is_empty() {
+                return Some(frame);
+            }
+            let Some(file) =
+                current.get(&(frame.file.root.as_path(), identity.relative_path.as_str()))
+            else {
+                return Some(frame);
+            };
+            let is_changed = match (&file.hash, &frame.file.hash) {
+                (Some(hash), Some(exported)) => hash != exported,
+                _ => file.identity != *identity,
+            };
+            if is_changed {
+                changed.insert(frame.file.root.join(&identity.relative_path));
+                return None;
+            }
+            frame.file.identity = file.identity.clone();
+            Some(frame)
+        })
+        .collect();
+    if !changed.is_empty() {
+        info!("{} files changed since the checkpoint", changed.len());
+    }
+    frames
+}
+
+/// Check the checkpoint was written by a run with the same settings as `header`.
+/// Differences are an error unless `force`, then they are only logged. `incremental` runs may
+/// change the model path and thresholds, which only apply to the files they process.
+pub fn check_checkpoint_header(
+    checkpoint: &Path,
+    header: &CheckpointHeader,
+    force: bool,
+    incremental: bool,
+) -> Result<()> {
+    let Some(mut checkpoint_header) = read_header(checkpoint)? else {
+        warn!(
+            "Checkpoint {} has no header, can't check it was written with the same settings",
+            checkpoint.display()
+        );
+        return Ok(());
+    };
+    if incremental {
+        let updated = CheckpointHeader {
+            model_path: header.model_path.clone(),
+            conf_thres: header.conf_thres,
+            iou_thres: header.iou_thres,
+            ..checkpoint_header.clone()
+        };
+        let differences = checkpoint_header.differences(&updated);
+        if !differences.is_empty() {
+            warn!(
+                "Updating export written with different settings, exported files keep their detections: {}",
+                differences.join(", ")
+            );
+        }
+        checkpoint_header = updated;
+    }
+    let differences = checkpoint_header.differences(header);
+    if differences.is_empty() {
+        return Ok(());
+    }
+    let differences = differences.join(", ");
+    if force {
+        warn!(
+            "Resuming checkpoint written with different settings: {}",
+            differences
+        );
+        Ok(())
+    } else {
+        error!(
+            "Checkpoint was written with different settings: {}",
+            differences
+        );
+        Err(anyhow::anyhow!(
+            "Checkpoint was written with different settings ({}), use --force-resume to resume anyway",
+            differences
+        ))
+    }
+}
+
+/// Frames of a checkpoint to keep on resume and the files they complete, by `FileItem::key`.
+/// Repeated `(file, frame_index)` frames are kept once. Frames of files with fewer frames than
+/// `total_frames`, like videos interrupted mid-way, are dropped so the file is processed again
+/// from scratch. Error records are dropped too with `retry_errors`.
+pub fn resume_frames(
+    frames: Vec<ExportFrame>,
+    retry_errors: bool,
+) -> (Vec<ExportFrame>, HashSet<FileKey>) {
+    let mut seen = HashSet::new();
+    let mut file_frames: HashMap<FileKey, Vec<ExportFrame>> = HashMap::new();
+    let mut order = Vec::new();
+    for frame in frames {
+        let key = frame.file.key();
+        if !seen.insert((key.clone(), frame.frame_index)) {
+            continue;
+        }
+        if !file_frames.contains_key(&key) {
+            order.push(key.clone());
+        }
+        file_frames.entry(key).or_default().push(frame);
+    }
+
+    let (mut partial, mut errors) = (0, 0);
+    let mut kept = Vec::new();
+    let mut done = HashSet::new();
+    for key in order {
+        let frames = file_frames.remove(&key).unwrap();
+        let is_error = frames
+            .iter()
+            .any(|f| f.error.as_ref().is_some_and(|e| !e.is_empty()));
+        if retry_errors && is_error {
+            errors += 1;
+            continue;
+        }
+        let total_frames = frames.iter().map(|f| f.total_frames).max().unwrap_or(0);
+        if frames.len() < total_frames {
+            partial += 1;
+            continue;
+        }
+        done.insert(key);
+        kept.extend(frames);
+    }
+    info!(
+        "Resuming with {} files done, {} partial files and {} error files to process again",
+        done.len(),
+        partial,
+        errors
+    );
+    (kept, done)
+}
+
+/// Load exported frames from a checkpoint into `export_data` and remove fully exported files
+/// from `all_files`
+pub fn resume_from_checkpoint<'a>(
+    checkpoint_path: &str,
+    header: &CheckpointHeader,
+    force: bool,
+    incremental: bool,
+    retry_errors: bool,
+    all_files: &'a mut HashSet<FileItem>,
+    export_data: &Arc<Mutex<Vec<ExportFrame>>>,
+) -> Result<&'a mut HashSet<FileItem>> {
+    let checkpoint = Path::new(checkpoint_path);
+    if !checkpoint.exists() {
+        error!("Checkpoint file does not exist");
+        return Err(anyhow::anyhow!("Checkpoint file does not exist"));
+    }
+    if !checkpoint.is_file() {
+        error!("Checkpoint path is not a file");
+        return Err(anyhow::anyhow!("Checkpoint path is not a file"));
+    }
+    match checkpoint.extension() {
+        Some(ext) => {
+            let ext = ext.to_str().unwrap();
+            if ext != "json" && ext != "csv" {
+                error!("Invalid checkpoint file extension: {}", ext);
+                Err(anyhow::anyhow!(
+                    "Invalid checkpoint file extension: {}",
+                    ext
+                ))
+            } else {
+                check_checkpoint_header(checkpoint, header, force, incremental)?;
+                let mut frames: Vec<ExportFrame> = if ext == "json" {
+                    let json = std::fs::read_to_string(checkpoint)?;
+                    serde_json::from_str(&json)
+                        .with_context(|| format!("Invalid checkpoint {}", checkpoint_path))?
+                } else {
+                    parse_export_csv(checkpoint)
+                        .with_context(|| format!("Invalid checkpoint {}", checkpoint_path))?
+                };
+                // Checkpoints written before multiple folders were supported have one root
+                if let Some(root) = header.roots.first() {
+                    for frame in &mut frames {
+                        if frame.file.root.as_os_str().is_empty() {
+                            frame.file.root = root.clone();
+                        }
+                    }
+                }
+                for frame in &mut frames {
+                    frame.resolve_from_root();
+                    // Buffer paths aren't exported, files are read from where they are
+                    frame.file.tmp_path = frame.file.file_path.clone();
+                }
+                let frames = drop_changed_files(frames, all_files);
+                let (frames, done) = resume_frames(frames, retry_errors);
+                // Files indexed now always have an identity, checkpoints written before
+                // identities existed are matched by path
+                all_files.retain(|file| {
+                    !done.contains(&file.key())
+                        && !done.contains(&FileKey::Path(file.file_path.clone()))
+                });
+                export_data.lock().unwrap().extend(frames);
+                Ok(all_files)
+            }
+        }
+        None => {
+            error!("Invalid checkpoint file extension");
+            Err(anyhow::anyhow!("Invalid checkpoint file extension"))
+        }
+    }
+}
+
+/// `path` with the `from` prefix replaced by `to`, or `None` if it doesn't start with `from`.
+/// Prefixes are compared as text and end at a `/` or `\` separator, so paths written on another
+/// OS are remapped too. Separators after the prefix follow `to`, `\` if it only has those.
+pub fn remap_path(path: &Path, from: &str, to: &str) -> Option<PathBuf> {
+    let from = from.trim_end_matches(['/', '\\']);
+    let path = path.to_string_lossy();
+    let rest = path.strip_prefix(from)?;
+    if from.is_empty() || !(rest.is_empty() || rest.starts_with(['/', '\\'])) {
+        return None;
+    }
+    let separator = if to.contains('\\') && !to.contains('/') {
+        "\\"
+    } else {
+        "/"
+    };
+    let to = to.trim_end_matches(['/', '\\']);
+    Some(PathBuf::from(format!(
+        "{}{}",
+        to,
+        rest.replace(['/', '\\'], separator)
+    )))
+}
+
+/// Rewrite the `from` prefix of the paths and roots in the export at `result_path` to `to`,
+/// e.g. after the data moved to another drive, and write it to `output`, which may be
+/// `result_path` itself. Its checkpoint header is rewritten along with it, so the moved folder
+/// can be resumed. Returns the number of frames changed.
+pub fn remap_export(result_path: &Path, from: &str, to: &str, output: &Path) -> Result<usize> {
+    let format = match result_path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => ExportFormat::Json,
+        Some("csv") => ExportFormat::Csv,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unknown export format of {}",
+                result_path.display()
+            ))
+        }
+    };
+    if !result_path.is_file() {
+        return Err(anyhow::anyhow!(
+            "Export {} does not exist",
+            result_path.display()
+        ));
+    }
+    let mut frames: Vec<ExportFrame> = match format {
+        ExportFormat::Json => serde_json::from_str(&std::fs::read_to_string(result_path)?)
+            .with_context(|| format!("Invalid export {}", result_path.display()))?,
+        ExportFormat::Csv => parse_export_csv(result_path)
+            .with_context(|| format!("Invalid export {}", result_path.display()))?,
+    };
+    let remap = |path: &mut PathBuf| match remap_path(path, from, to) {
+        Some(remapped) => {
+            *path = remapped;
+            true
+        }
+        None => false,
+    };
+    let mut changed = 0;
+    for frame in &mut frames {
+        // Paths exported with `--relative-paths` only change with their root
+        let mut remapped = remap(&mut frame.file.root);
+        remapped |= remap(&mut frame.file.file_path);
+        if let Some(path) = &mut frame.duplicate_of {
+            remapped |= remap(path);
+        }
+        if let Some(path) = &mut frame.near_duplicate_of {
+            remapped |= remap(path);
+        }
+        if remapped {
+            changed += 1;
+        }
+    }
+    let header = read_header(result_path)?;
+    match format {
+        ExportFormat::Json => write_json(&frames, output, false)?,
+        ExportFormat::Csv => write_csv(&frames, output, false)?,
+    }
+    if let Some(mut header) = header {
+        for root in &mut header.roots {
+            remap(root);
+        }
+        write_header(output, &header)?;
+    }
+    Ok(changed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn test_parse_export_csv() {
-        // let csv = Path::new("input/result.csv");
-        let export_data = parse_export_csv("input/result.csv").unwrap();
-        assert_eq!(export_data.len(), 11);
+        // Checkpoint written before time and metadata columns were added
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("result.csv");
+        std::fs::write(
+            &csv,
+            r#"folder_id,file_id,file_path,shoot_time,frame_index,total_frames,bboxes,label,error
+1,0,/data/deer.jpg,2024-05-01 06:30:00 +08:00,0,1,"[{""x1"":1.0,""y1"":2.0,""x2"":3.0,""y2"":4.0,""score"":0.9,""class"":0}]",Animal,
+1,1,/data/clip.mp4,2024-05-01 06:31:00 +08:00,0,2,[],Blank,
+1,1,/data/clip.mp4,2024-05-01 06:31:00 +08:00,1,2,[],Blank,
+"#,
+        )
+        .unwrap();
+        let export_data = parse_export_csv(&csv).unwrap();
+        assert_eq!(export_data.len(), 3);
+        assert_eq!(
+            export_data[0].file.file_path,
+            PathBuf::from("/data/deer.jpg")
+        );
+        assert_eq!(export_data[0].bboxes.as_ref().unwrap()[0].score, 0.9);
+        assert_eq!(export_data[0].time_source, None);
+        assert_eq!(export_data[2].frame_index, 1);
+        assert_eq!(export_data[2].total_frames, 2);
+        assert_eq!(export_data[2].metadata, MediaMetadata::default());
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let folder = std::env::temp_dir().join("md5rs_test_csv_round_trip");
+        std::fs::create_dir_all(&folder).unwrap();
+        let frames = vec![ExportFrame {
+            file: FileItem::new(1, 2, folder.join("video.mp4"), None)
+                .with_root(&folder)
+                .with_identity(FileIdentity {
+                    relative_path: "site_a/video.mp4".to_string(),
+                    size: 1024,
+                    mtime: Some(1704038400),
+                }),
+            shoot_time: Some("2024-01-01 00:00:00 +08:00".to_string()),
+            time_source: Some(TimeSource::Container),
+            frame_index: 3,
+            total_frames: 4,
+            frame_time: Some(1.5),
+            frame_shoot_time: Some("2024-01-01 00:00:01.500 +08:00".to_string()),
+            bboxes: Some(vec![]),
+            label: Some(HashSet::from(["Blank".to_string()])),
+            error: Some("".to_string()),
+            metadata: MediaMetadata {
+                make: Some("RECONYX".to_string()),
+                latitude: Some(-33.5),
+                temperature: Some(-4),
+                moon_phase: Some("Full".to_string()),
+                sequence_number: Some(2),
+                ..Default::default()
+            },
+            duplicate_of: Some(folder.join("copy").join("video.mp4")),
+            perceptual_hash: Some("f0e1d2c3b4a59687".to_string()),
+            near_duplicate_of: None,
+        }];
+        write_csv(&frames, &folder.join("result.csv"), false).unwrap();
+        let parsed = parse_export_csv(folder.join("result.csv")).unwrap();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].file, frames[0].file);
+        assert_eq!(parsed[0].frame_index, 3);
+        assert_eq!(parsed[0].total_frames, 4);
+        assert_eq!(parsed[0].time_source, Some(TimeSource::Container));
+        assert_eq!(parsed[0].frame_time, Some(1.5));
+        assert_eq!(parsed[0].frame_shoot_time, frames[0].frame_shoot_time);
+        assert_eq!(parsed[0].label, frames[0].label);
+        assert_eq!(parsed[0].metadata, frames[0].metadata);
+        assert_eq!(parsed[0].duplicate_of, frames[0].duplicate_of);
+        assert_eq!(parsed[0].perceptual_hash, frames[0].perceptual_hash);
+        assert_eq!(parsed[0].near_duplicate_of, None);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_header_differences() {
+        let header = CheckpointHeader {
+            version: "0.1.3".to_string(),
+            model: "mdv5a".to_string(),
+            model_path: PathBuf::from("models/md_v5a_d_pp.onnx"),
+            imgsz: 1280,
+            conf_thres: 0.2,
+            iou_thres: 0.45,
+            roots: vec![PathBuf::from("/data/site_a")],
+        };
+        let other = CheckpointHeader {
+            version: "0.1.4".to_string(),
+            conf_thres: 0.3,
+            roots: vec![PathBuf::from("/data/site_a"), PathBuf::from("/data/site_b")],
+            ..header.clone()
+        };
+        assert!(header.differences(&header).is_empty());
+        assert_eq!(
+            header.differences(&other),
+            [
+                "conf_thres: 0.2 != 0.3",
+                "roots: /data/site_a != /data/site_a, /data/site_b"
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let result = dir.path().join("result.csv");
+        assert_eq!(read_header(&result).unwrap(), None);
+        write_header(&result, &header).unwrap();
+        assert_eq!(read_header(&result).unwrap(), Some(header.clone()));
+        assert!(check_checkpoint_header(&result, &header, false, false).is_ok());
+        assert!(check_checkpoint_header(&result, &other, false, false).is_err());
+        assert!(check_checkpoint_header(&result, &other, true, false).is_ok());
+        // Incremental runs may change thresholds, but not the folders
+        let thresholds = CheckpointHeader {
+            conf_thres: 0.3,
+            model_path: PathBuf::from("/models/md_v5a_d_pp.onnx"),
+            ..header.clone()
+        };
+        assert!(check_checkpoint_header(&result, &thresholds, false, false).is_err());
+        assert!(check_checkpoint_header(&result, &thresholds, false, true).is_ok());
+        assert!(check_checkpoint_header(&result, &other, false, true).is_err());
+
+        // Headers written before multiple folders were supported have one root
+        let mut legacy = serde_json::to_value(&header).unwrap();
+        let roots = legacy.as_object_mut().unwrap().remove("roots").unwrap();
+        legacy["root"] = roots[0].clone();
+        let legacy: CheckpointHeader = serde_json::from_value(legacy).unwrap();
+        assert_eq!(legacy, header);
+    }
+
+    #[test]
+    fn test_output_path() {
+        let names = OutputNames {
+            folder: "site_a".to_string(),
+            model: "mdv5a".to_string(),
+            timestamp: "20240501-063000".to_string(),
+        };
+        let path = |template: &str, format: &ExportFormat| output_path(template, format, &names);
+        assert_eq!(
+            path("{folder}_result", &ExportFormat::Json).unwrap(),
+            PathBuf::from("site_a_result.json")
+        );
+        assert_eq!(
+            path("out/{model}_{timestamp}.csv", &ExportFormat::Csv).unwrap(),
+            PathBuf::from("out/mdv5a_20240501-063000.csv")
+        );
+        // Other extensions are kept
+        assert_eq!(
+            path("{model}.v2", &ExportFormat::Csv).unwrap(),
+            PathBuf::from("mdv5a.v2.csv")
+        );
+        assert_eq!(
+            path("out/", &ExportFormat::Csv).unwrap(),
+            PathBuf::from("out/result.csv")
+        );
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            path(dir.path().to_str().unwrap(), &ExportFormat::Json).unwrap(),
+            dir.path().join("result.json")
+        );
+        assert!(path("result.json", &ExportFormat::Csv).is_err());
+        assert!(path("{date}_result", &ExportFormat::Csv).is_err());
+        assert!(path("{model", &ExportFormat::Csv).is_err());
+    }
+
+    #[test]
+    fn test_folder_name() {
+        assert_eq!(folder_name(Path::new("/data/site_a")), "site_a");
+        assert_eq!(folder_name(Path::new("/")), "root");
+        #[cfg(windows)]
+        {
+            assert_eq!(folder_name(Path::new("E:\\")), "E");
+            assert_eq!(folder_name(Path::new("\\\\?\\E:\\")), "E");
+        }
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("result.json");
+        std::fs::write(&path, "old").unwrap();
+
+        // A failed write keeps the previous file
+        let failed = write_atomic(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(anyhow::anyhow!("crash"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.path().join(".result.json.tmp").exists());
+
+        write_atomic(&path, |writer| {
+            writer.write_all(b"new")?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_move_previous_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site_result.csv");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::write(header_path(&path), "{}").unwrap();
+
+        let previous = move_previous_export(&path).unwrap();
+        assert_eq!(previous, dir.path().join("site_result.previous.csv"));
+        assert_eq!(std::fs::read_to_string(&previous).unwrap(), "old");
+        assert!(header_path(&previous).exists());
+        assert!(!path.exists());
+        assert!(!header_path(&path).exists());
+    }
+
+    fn resume_frame(path: &str, frame_index: usize, total_frames: usize) -> ExportFrame {
+        ExportFrame {
+            frame_index,
+            total_frames,
+            ..ExportFrame::detected(FileItem::new(0, 0, PathBuf::from(path), None), &["Blank"])
+        }
+    }
+
+    #[test]
+    fn test_resume_frames() {
+        let frames = vec![
+            resume_frame("a.jpg", 0, 1),
+            // Written twice by overlapping checkpoints
+            resume_frame("a.jpg", 0, 1),
+            resume_frame("clip.mp4", 0, 3),
+            resume_frame("clip.mp4", 1, 3),
+            resume_frame("full.mp4", 0, 2),
+            resume_frame("full.mp4", 1, 2),
+            ExportFrame::from_error(
+                FileItem::new(0, 0, PathBuf::from("broken.jpg"), None),
+                "Failed to decode".to_string(),
+            ),
+        ];
+
+        let (kept, done) = resume_frames(frames.clone(), false);
+        let kept: Vec<_> = kept
+            .iter()
+            .map(|f| (f.file.file_path.to_str().unwrap(), f.frame_index))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("a.jpg", 0),
+                ("full.mp4", 0),
+                ("full.mp4", 1),
+                ("broken.jpg", 0)
+            ]
+        );
+        assert!(!done.contains(&FileKey::Path(PathBuf::from("clip.mp4"))));
+        assert!(done.contains(&FileKey::Path(PathBuf::from("broken.jpg"))));
+
+        let (kept, done) = resume_frames(frames, true);
+        assert_eq!(kept.len(), 3);
+        assert_eq!(done.len(), 2);
+        assert!(!done.contains(&FileKey::Path(PathBuf::from("broken.jpg"))));
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let root = PathBuf::from("/data/site_a");
+        let mut frame = ExportFrame::detected(
+            FileItem::new(1, 0, root.join("cam1").join("b.jpg"), None).with_root(&root),
+            &["Blank"],
+        );
+        frame.duplicate_of = Some(PathBuf::from("/data/site_b/a.jpg"));
+        frame.near_duplicate_of = Some(root.join("cam1").join("a.jpg"));
+        let relative = frame.relative_to_root();
+        assert_eq!(relative.file.file_path, PathBuf::from("cam1/b.jpg"));
+        // A duplicate under another root stays absolute
+        assert_eq!(relative.duplicate_of, frame.duplicate_of);
+        assert_eq!(
+            relative.near_duplicate_of,
+            Some(PathBuf::from("cam1/a.jpg"))
+        );
+
+        let mut resolved = relative.into_owned();
+        resolved.resolve_from_root();
+        assert_eq!(resolved.file.file_path, frame.file.file_path);
+        assert_eq!(resolved.duplicate_of, frame.duplicate_of);
+        assert_eq!(resolved.near_duplicate_of, frame.near_duplicate_of);
+
+        // Frames without a root are exported as they are
+        let frame = ExportFrame::detected(
+            FileItem::new(1, 0, PathBuf::from("/data/a.jpg"), None),
+            &["Blank"],
+        );
+        assert!(matches!(frame.relative_to_root(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_remap_path() {
+        let remap = |path: &str, from: &str, to: &str| {
+            remap_path(Path::new(path), from, to).map(|path| path.to_string_lossy().into_owned())
+        };
+        assert_eq!(
+            remap("/mnt/data/site_a/a.jpg", "/mnt/data", "/media/usb"),
+            Some("/media/usb/site_a/a.jpg".to_string())
+        );
+        assert_eq!(
+            remap("/mnt/data", "/mnt/data/", "/media/usb/"),
+            Some("/media/usb".to_string())
+        );
+        // Prefixes end at a separator
+        assert_eq!(remap("/mnt/data2/a.jpg", "/mnt/data", "/media/usb"), None);
+        assert_eq!(remap("a.jpg", "/mnt/data", "/media/usb"), None);
+        // Windows paths on another OS and back
+        assert_eq!(
+            remap("D:\\camera\\site_a\\a.jpg", "D:\\camera", "/mnt/camera"),
+            Some("/mnt/camera/site_a/a.jpg".to_string())
+        );
+        assert_eq!(
+            remap("/mnt/camera/site_a/a.jpg", "/mnt/camera", "E:\\camera"),
+            Some("E:\\camera\\site_a\\a.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remap_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = PathBuf::from("/mnt/data");
+        let mut moved = ExportFrame::detected(
+            FileItem::new(1, 0, root.join("a.jpg"), None).with_root(&root),
+            &["Blank"],
+        );
+        moved.duplicate_of = Some(root.join("b.jpg"));
+        let other = ExportFrame::detected(
+            FileItem::new(1, 1, PathBuf::from("/srv/c.jpg"), None).with_root(Path::new("/srv")),
+            &["Blank"],
+        );
+        let result = dir.path().join("result.json");
+        write_json(&[moved, other], &result, false).unwrap();
+        let header = CheckpointHeader {
+            version: "0.1.4".to_string(),
+            model: "mdv5a".to_string(),
+            model_path: PathBuf::from("models/md_v5a_d_pp.onnx"),
+            imgsz: 1280,
+            conf_thres: 0.2,
+            iou_thres: 0.45,
+            roots: vec![root.clone(), PathBuf::from("/srv")],
+        };
+        write_header(&result, &header).unwrap();
+
+        let output = dir.path().join("moved.json");
+        assert_eq!(
+            remap_export(&result, "/mnt/data", "/media/usb", &output).unwrap(),
+            1
+        );
+        let frames: Vec<ExportFrame> =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(frames[0].file.root, PathBuf::from("/media/usb"));
+        assert_eq!(frames[0].file.file_path, PathBuf::from("/media/usb/a.jpg"));
+        assert_eq!(
+            frames[0].duplicate_of,
+            Some(PathBuf::from("/media/usb/b.jpg"))
+        );
+        assert_eq!(frames[1].file.file_path, PathBuf::from("/srv/c.jpg"));
+        assert_eq!(
+            read_header(&output).unwrap().unwrap().roots,
+            [PathBuf::from("/media/usb"), PathBuf::from("/srv")]
+        );
+        // The original export is left as it was
+        assert_eq!(read_header(&result).unwrap(), Some(header));
+
+        assert!(remap_export(&dir.path().join("result.txt"), "/a", "/b", &output).is_err());
     }
 }
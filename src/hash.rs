@@ -0,0 +1,709 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use crossbeam_channel::{Receiver, Sender};
+use image::metadata::Orientation;
+use ndarray::Array3;
+use rayon::prelude::*;
+use tracing::{info, warn};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::export::ExportFrame;
+use crate::letterbox::Letterbox;
+use crate::media::{ArrayItem, Frame};
+use crate::utils::FileItem;
+use crate::NearDuplicates;
+
+/// XXH3 128-bit hash of the file content as 32 hex digits
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:032x}", hasher.digest128()))
+}
+
+/// Set the content hash of every file not hashed yet, in parallel. Files that can't be read
+/// keep no hash and fail later when they are decoded.
+pub fn hash_files(files: &mut [FileItem]) {
+    let unhashed = files.iter().filter(|file| file.hash.is_none()).count();
+    if unhashed > 0 {
+        info!("Hashing {} files", unhashed);
+    }
+    files
+        .par_iter_mut()
+        .filter(|file| file.hash.is_none())
+        .for_each(|file| match hash_file(&file.file_path) {
+            Ok(hash) => file.hash = Some(hash),
+            Err(e) => warn!("Failed to hash {:?}: {}", file.file_path, e),
+        });
+}
+
+/// Files left to detect after exact duplicates are set aside
+pub struct Deduplicated {
+    pub unique: Vec<FileItem>,
+    /// Each duplicate with the path of the first file of the same content
+    pub duplicates: Vec<(FileItem, PathBuf)>,
+}
+
+/// Split hashed `files` into the first file of each content and its duplicates.
+/// Files already in `exported`, like those of a checkpoint, come first so new copies of them
+/// reuse their detections. Otherwise the first file in walk order is kept.
+pub fn find_duplicates(mut files: Vec<FileItem>, exported: &[ExportFrame]) -> Deduplicated {
+    let mut originals: HashMap<String, PathBuf> = HashMap::new();
+    for frame in exported {
+        if let (Some(hash), None) = (&frame.file.hash, &frame.duplicate_of) {
+            originals
+                .entry(hash.clone())
+                .or_insert_with(|| frame.file.file_path.clone());
+        }
+    }
+
+    files.sort_by_key(|file| (file.folder_id, file.file_id));
+    let mut unique = Vec::new();
+    let mut duplicates = Vec::new();
+    for file in files {
+        let Some(hash) = file.hash.clone() else {
+            unique.push(file);
+            continue;
+        };
+        match originals.get(&hash) {
+            Some(original) => duplicates.push((file, original.clone())),
+            None => {
+                originals.insert(hash, file.file_path.clone());
+                unique.push(file);
+            }
+        }
+    }
+
+    let mut groups: HashMap<&PathBuf, Vec<&PathBuf>> = HashMap::new();
+    for (file, original) in &duplicates {
+        groups.entry(original).or_default().push(&file.file_path);
+    }
+    for (original, copies) in &groups {
+        info!("Duplicates of {:?}: {:?}", original, copies);
+    }
+    info!(
+        "Found {} duplicate files of {} files",
+        duplicates.len(),
+        groups.len()
+    );
+    Deduplicated { unique, duplicates }
+}
+
+/// Export the frames of each duplicate's original again for the duplicate
+pub fn copy_duplicates(export_data: &mut Vec<ExportFrame>, duplicates: &[(FileItem, PathBuf)]) {
+    let mut frames_of: HashMap<&Path, Vec<&ExportFrame>> = HashMap::new();
+    for frame in export_data.iter() {
+        frames_of
+            .entry(frame.file.file_path.as_path())
+            .or_default()
+            .push(frame);
+    }
+    let mut copies = Vec::new();
+    for (file, original) in duplicates {
+        let Some(frames) = frames_of.get(original.as_path()) else {
+            warn!(
+                "No detections of {:?} to copy to {:?}",
+                original, file.file_path
+            );
+            continue;
+        };
+        for frame in frames {
+            copies.push(ExportFrame {
+                file: file.clone(),
+                duplicate_of: Some(original.clone()),
+                ..(*frame).clone()
+            });
+        }
+    }
+    export_data.extend(copies);
+}
+
+/// Difference hash of the image part of a letterboxed frame. The image is shrunk to 9x8 grey
+/// cells by averaging and each bit tells whether a cell is brighter than its right neighbour,
+/// so small changes like sensor noise or a moving leaf flip few bits.
+pub fn dhash(data: &Array3<u8>, letterbox: &Letterbox) -> u64 {
+    let (left, top) = (letterbox.pad.0 as usize, letterbox.pad.1 as usize);
+    let (width, height) = (letterbox.resized.0 as usize, letterbox.resized.1 as usize);
+    let mut sums = [[0u64; 9]; 8];
+    let mut counts = [[0u64; 9]; 8];
+    for y in 0..height {
+        let row = y * 8 / height;
+        for x in 0..width {
+            let col = x * 9 / width;
+            let (y, x) = (top + y, left + x);
+            sums[row][col] +=
+                data[[y, x, 0]] as u64 + data[[y, x, 1]] as u64 + data[[y, x, 2]] as u64;
+            counts[row][col] += 1;
+        }
+    }
+    let mean = |row: usize, col: usize| sums[row][col] as f32 / counts[row][col].max(1) as f32;
+    let mut hash = 0;
+    for row in 0..8 {
+        for col in 0..8 {
+            hash = hash << 1 | (mean(row, col) > mean(row, col + 1)) as u64;
+        }
+    }
+    hash
+}
+
+/// Settings of near-duplicate detection in bursts
+#[derive(Debug, Clone, Copy)]
+pub struct NearDuplicateConfig {
+    pub mode: NearDuplicates,
+    /// Most differing hash bits of near-identical images
+    pub max_distance: u32,
+    /// Most seconds between the shoot times of images of a burst
+    pub burst_window: f32,
+}
+
+/// Image starting a group of near-identical images
+struct BurstImage {
+    hash: u64,
+    shoot_time: DateTime<Local>,
+    size: (usize, usize),
+    orientation: Orientation,
+    file_path: PathBuf,
+}
+
+/// Perceptual hashes of the images seen so far and the groups of near-identical images.
+/// Images are compared to the first image of each group in the same folder, which must have
+/// the same size and orientation and a shoot time within the burst window.
+pub struct BurstIndex {
+    config: NearDuplicateConfig,
+    /// First images of the groups of each folder, without those before the burst window
+    first_images: HashMap<usize, Vec<BurstImage>>,
+    /// Perceptual hash of each image
+    pub hashes: HashMap<PathBuf, u64>,
+    /// The first image of the group of each near-duplicate
+    pub near_duplicate_of: HashMap<PathBuf, PathBuf>,
+    /// Near-duplicates not sent to detection, waiting for the detections of their first image
+    pub skipped: Vec<ExportFrame>,
+}
+
+impl BurstIndex {
+    pub fn new(config: NearDuplicateConfig) -> Self {
+        BurstIndex {
+            config,
+            first_images: HashMap::new(),
+            hashes: HashMap::new(),
+            near_duplicate_of: HashMap::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Hash an image and return the first image of its group if it is a near-duplicate.
+    /// Otherwise it starts a group. Images without shoot time are never grouped.
+    pub fn insert(&mut self, frame: &Frame) -> Option<PathBuf> {
+        let hash = dhash(&frame.data, &frame.letterbox);
+        self.hashes.insert(frame.file.file_path.clone(), hash);
+        let shoot_time = frame.shoot_time?;
+        let size = (frame.width, frame.height);
+        let window_ms = (self.config.burst_window * 1000.0) as i64;
+        let first_images = self.first_images.entry(frame.file.folder_id).or_default();
+        first_images
+            .retain(|image| (shoot_time - image.shoot_time).num_milliseconds() <= window_ms);
+        let first = first_images.iter().find(|image| {
+            image.size == size
+                && image.orientation == frame.orientation
+                && (image.shoot_time - shoot_time).num_milliseconds().abs() <= window_ms
+                && (image.hash ^ hash).count_ones() <= self.config.max_distance
+        });
+        match first {
+            Some(first) => {
+                self.near_duplicate_of
+                    .insert(frame.file.file_path.clone(), first.file_path.clone());
+                Some(first.file_path.clone())
+            }
+            None => {
+                first_images.push(BurstImage {
+                    hash,
+                    shoot_time,
+                    size,
+                    orientation: frame.orientation,
+                    file_path: frame.file.file_path.clone(),
+                });
+                None
+            }
+        }
+    }
+}
+
+/// Still image, unlike video frames which are sampled already
+fn is_still_image(item: &ArrayItem) -> bool {
+    matches!(item, ArrayItem::Frame(frame) if frame.total_frames == 1 && frame.frame_time.is_none())
+}
+
+/// Decoded images held back by [`WalkOrder`] at most, about 5 MB each at 1280 px
+const MAX_HELD_IMAGES: usize = 32;
+
+/// Files of a folder in walk order, with the first item decoded of each
+#[derive(Default)]
+struct FolderQueue {
+    files: VecDeque<PathBuf>,
+    /// Still images waiting for the files before them, `None` for files with other items
+    arrived: HashMap<PathBuf, Option<ArrayItem>>,
+}
+
+impl FolderQueue {
+    /// Move the arrived items at the front into `released`, returning the number of images
+    fn release(&mut self, released: &mut Vec<ArrayItem>) -> usize {
+        let mut images = 0;
+        while let Some(item) = self
+            .files
+            .front()
+            .and_then(|path| self.arrived.remove(path))
+        {
+            self.files.pop_front();
+            images += item.is_some() as usize;
+            released.extend(item);
+        }
+        images
+    }
+
+    fn holds_images(&self) -> bool {
+        self.arrived.values().any(Option::is_some)
+    }
+}
+
+/// Puts the still images of each folder back in walk order, whichever thread decodes them
+/// first, so the groups of near-identical images don't depend on timing. An image waits until
+/// the first item of every file before it in its folder arrived, like the first frame of a
+/// video. Other items pass at once. Past `max_held` images the first folder holding any gives
+/// up the order of the files still missing before them, which then pass at once too.
+struct WalkOrder {
+    folders: BTreeMap<usize, FolderQueue>,
+    /// Files without any item yet
+    waiting: HashSet<PathBuf>,
+    held: usize,
+    max_held: usize,
+}
+
+impl WalkOrder {
+    fn new(files: &[FileItem], max_held: usize) -> Self {
+        let mut files: Vec<&FileItem> = files.iter().collect();
+        files.sort_by_key(|file| (file.folder_id, file.file_id));
+        let mut folders: BTreeMap<usize, FolderQueue> = BTreeMap::new();
+        for file in &files {
+            folders
+                .entry(file.folder_id)
+                .or_default()
+                .files
+                .push_back(file.file_path.clone());
+        }
+        let waiting = files.iter().map(|file| file.file_path.clone()).collect();
+        WalkOrder {
+            folders,
+            waiting,
+            held: 0,
+            max_held,
+        }
+    }
+
+    /// Items released by the arrival of `item`, in walk order
+    fn push(&mut self, item: ArrayItem) -> Vec<ArrayItem> {
+        let file = match &item {
+            ArrayItem::Frame(frame) => &frame.file,
+            ArrayItem::ErrFile(err_file) => &err_file.file,
+        };
+        if !self.waiting.remove(&file.file_path) {
+            return vec![item];
+        }
+        let folder_id = file.folder_id;
+        let path = file.file_path.clone();
+        let folder = self.folders.get_mut(&folder_id).unwrap();
+        let mut released = Vec::new();
+        if is_still_image(&item) {
+            folder.arrived.insert(path, Some(item));
+            self.held += 1;
+        } else {
+            folder.arrived.insert(path, None);
+            released.push(item);
+        }
+        self.held -= folder.release(&mut released);
+        if folder.files.is_empty() {
+            self.folders.remove(&folder_id);
+        }
+        while self.held > self.max_held {
+            let (&folder_id, folder) = self
+                .folders
+                .iter_mut()
+                .find(|(_, folder)| folder.holds_images())
+                .unwrap();
+            while let Some(path) = folder
+                .files
+                .front()
+                .filter(|path| !folder.arrived.contains_key(*path))
+                .cloned()
+            {
+                self.waiting.remove(&path);
+                folder.files.pop_front();
+            }
+            self.held -= folder.release(&mut released);
+            if folder.files.is_empty() {
+                self.folders.remove(&folder_id);
+            }
+        }
+        released
+    }
+
+    /// Images still waiting for files that never sent anything, in walk order
+    fn finish(self) -> Vec<ArrayItem> {
+        self.folders
+            .into_values()
+            .flat_map(|mut folder| {
+                folder
+                    .files
+                    .into_iter()
+                    .filter_map(move |path| folder.arrived.remove(&path).flatten())
+            })
+            .collect()
+    }
+}
+
+/// Pass decoded frames from `rx` on to detection, hashing images of `files` on the way in walk
+/// order. With [`NearDuplicates::Skip`] near-duplicates are held back instead of detected.
+pub fn near_duplicate_worker(
+    config: NearDuplicateConfig,
+    files: &[FileItem],
+    rx: Receiver<ArrayItem>,
+    tx: Sender<ArrayItem>,
+) -> JoinHandle<BurstIndex> {
+    let mut order = WalkOrder::new(files, MAX_HELD_IMAGES);
+    thread::spawn(move || {
+        let mut index = BurstIndex::new(config);
+        let pass = |item: ArrayItem, index: &mut BurstIndex| {
+            if let ArrayItem::Frame(frame) = &item {
+                if is_still_image(&item) {
+                    let first = index.insert(frame);
+                    if let (Some(first), NearDuplicates::Skip) = (first, config.mode) {
+                        index.skipped.push(ExportFrame {
+                            near_duplicate_of: Some(first),
+                            ..ExportFrame::from_frame(frame, vec![], HashSet::new())
+                        });
+                        return;
+                    }
+                }
+            }
+            tx.send(item).expect("Send frame to detection failed");
+        };
+        for item in rx {
+            for item in order.push(item) {
+                pass(item, &mut index);
+            }
+        }
+        for item in order.finish() {
+            pass(item, &mut index);
+        }
+        index
+    })
+}
+
+/// Export the perceptual hash and the group of each image, and give the skipped
+/// near-duplicates the detections of the first image of their group
+pub fn apply_near_duplicates(export_data: &mut Vec<ExportFrame>, index: BurstIndex) {
+    for frame in export_data.iter_mut() {
+        let path = &frame.file.file_path;
+        if let Some(hash) = index.hashes.get(path) {
+            frame.perceptual_hash = Some(format!("{:016x}", hash));
+        }
+        if let Some(first) = index.near_duplicate_of.get(path) {
+            frame.near_duplicate_of = Some(first.clone());
+        }
+    }
+    let detected: HashMap<&Path, &ExportFrame> = export_data
+        .iter()
+        .map(|frame| (frame.file.file_path.as_path(), frame))
+        .collect();
+    let mut copies = Vec::new();
+    for mut frame in index.skipped {
+        let path = &frame.file.file_path;
+        frame.perceptual_hash = index.hashes.get(path).map(|hash| format!("{:016x}", hash));
+        let first = frame.near_duplicate_of.as_deref().unwrap_or(path);
+        match detected.get(first) {
+            Some(first) => {
+                frame.bboxes = first.bboxes.clone();
+                frame.label = first.label.clone();
+                frame.error = first.error.clone();
+            }
+            None => {
+                warn!("No detections of {:?} to copy to {:?}", first, path);
+                frame.error = Some(format!("No detections of near-duplicate {:?}", first));
+            }
+        }
+        copies.push(frame);
+    }
+    info!(
+        "Found {} near-duplicate images, {} not detected",
+        index.near_duplicate_of.len(),
+        copies.len()
+    );
+    export_data.extend(copies);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stamp::StampMask;
+    use crate::utils::PadAlign;
+    use chrono::TimeZone;
+
+    fn hashed(file_id: usize, path: &str, hash: &str) -> FileItem {
+        FileItem {
+            hash: Some(hash.to_string()),
+            ..FileItem::new(0, file_id, PathBuf::from(path), None)
+        }
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, c) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("c"),
+        );
+        std::fs::write(&a, vec![7u8; 3 << 20]).unwrap();
+        std::fs::write(&b, vec![7u8; 3 << 20]).unwrap();
+        std::fs::write(&c, vec![8u8; 3 << 20]).unwrap();
+        let hash = hash_file(&a).unwrap();
+        assert_eq!(hash.len(), 32);
+        assert_eq!(hash, hash_file(&b).unwrap());
+        assert_ne!(hash, hash_file(&c).unwrap());
+    }
+
+    #[test]
+    fn test_find_and_copy_duplicates() {
+        let exported_file = hashed(0, "/old/a.jpg", "aa");
+        let mut export_data = vec![ExportFrame::detected(exported_file, &["Animal"])];
+        let files = vec![
+            hashed(3, "/new/b2.jpg", "bb"),
+            hashed(2, "/new/b1.jpg", "bb"),
+            hashed(1, "/new/a.jpg", "aa"),
+            FileItem::new(0, 4, PathBuf::from("/new/unreadable.jpg"), None),
+        ];
+
+        let deduplicated = find_duplicates(files, &export_data);
+        let unique: Vec<_> = deduplicated
+            .unique
+            .iter()
+            .map(|f| f.file_path.to_str().unwrap())
+            .collect();
+        assert_eq!(unique, ["/new/b1.jpg", "/new/unreadable.jpg"]);
+        let duplicates: Vec<_> = deduplicated
+            .duplicates
+            .iter()
+            .map(|(f, original)| (f.file_path.to_str().unwrap(), original.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            duplicates,
+            [("/new/a.jpg", "/old/a.jpg"), ("/new/b2.jpg", "/new/b1.jpg")]
+        );
+
+        // b1.jpg is detected as usual, then both duplicates get copies
+        export_data.push(ExportFrame::from_error(
+            hashed(2, "/new/b1.jpg", "bb"),
+            "Failed to decode".to_string(),
+        ));
+        copy_duplicates(&mut export_data, &deduplicated.duplicates);
+        assert_eq!(export_data.len(), 4);
+        let a = &export_data[2];
+        assert_eq!(a.file.file_path, PathBuf::from("/new/a.jpg"));
+        assert_eq!(a.duplicate_of, Some(PathBuf::from("/old/a.jpg")));
+        assert_eq!(a.label, export_data[0].label);
+        assert_eq!(export_data[3].error.as_deref(), Some("Failed to decode"));
+    }
+
+    /// 64x48 still image letterboxed to 64, brightness rising to the right with a bright
+    /// square at `spot`
+    fn burst_frame(path: &str, second: u32, spot: Option<(usize, usize)>) -> Frame {
+        let letterbox = Letterbox::new(64, 48, &StampMask::default(), 64, PadAlign::Center);
+        let mut data = Array3::from_elem((64, 64, 3), 114u8);
+        for y in 0..48 {
+            for x in 0..64 {
+                let value = if spot.is_some_and(|(sx, sy)| x / 16 == sx && y / 16 == sy) {
+                    255
+                } else {
+                    x as u8 * 2
+                };
+                data.slice_mut(ndarray::s![y + 8, x, ..]).fill(value);
+            }
+        }
+        Frame {
+            file: FileItem::new(0, 0, PathBuf::from(path), None),
+            data,
+            width: 64,
+            height: 48,
+            letterbox,
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: None,
+            shoot_time: Local.with_ymd_and_hms(2024, 5, 1, 6, 30, second).single(),
+            time_source: None,
+            orientation: Orientation::NoTransforms,
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_dhash() {
+        let plain = burst_frame("a.jpg", 0, None);
+        let hash = dhash(&plain.data, &plain.letterbox);
+        // Brightness rises to the right, so no cell is brighter than its right neighbour
+        assert_eq!(hash, 0);
+        // Padding rows don't count
+        let mut padded = plain.data.clone();
+        padded.slice_mut(ndarray::s![..8, .., ..]).fill(0);
+        assert_eq!(dhash(&padded, &plain.letterbox), hash);
+
+        let spot = burst_frame("b.jpg", 0, Some((1, 1)));
+        assert!(dhash(&spot.data, &spot.letterbox).count_ones() > 0);
+    }
+
+    #[test]
+    fn test_burst_index() {
+        let mut index = BurstIndex::new(NearDuplicateConfig {
+            mode: NearDuplicates::Skip,
+            max_distance: 4,
+            burst_window: 10.0,
+        });
+        assert_eq!(index.insert(&burst_frame("a.jpg", 0, None)), None);
+        assert_eq!(
+            index.insert(&burst_frame("b.jpg", 5, None)),
+            Some(PathBuf::from("a.jpg"))
+        );
+        // Outside the burst window, which drops a.jpg from the folder
+        assert_eq!(index.insert(&burst_frame("c.jpg", 30, None)), None);
+        assert_eq!(index.first_images[&0].len(), 1);
+        // An animal walked in
+        assert_eq!(index.insert(&burst_frame("d.jpg", 2, Some((1, 1)))), None);
+        // Another folder
+        let mut other = burst_frame("e.jpg", 1, None);
+        other.file.folder_id = 1;
+        assert_eq!(index.insert(&other), None);
+        // No shoot time
+        let mut untimed = burst_frame("f.jpg", 1, None);
+        untimed.shoot_time = None;
+        assert_eq!(index.insert(&untimed), None);
+        assert_eq!(index.hashes.len(), 6);
+        assert_eq!(index.near_duplicate_of.len(), 1);
+    }
+
+    #[test]
+    fn test_walk_order() {
+        let frame = |folder_id, file_id, path: &str| {
+            let mut frame = burst_frame(path, 0, None);
+            frame.file = FileItem::new(folder_id, file_id, PathBuf::from(path), None);
+            frame
+        };
+        let (a, b, c, d) = (
+            frame(0, 0, "a.jpg"),
+            frame(0, 1, "b.mp4"),
+            frame(0, 2, "c.jpg"),
+            frame(1, 0, "d.jpg"),
+        );
+        let files: Vec<FileItem> = [&d, &c, &b, &a].iter().map(|f| f.file.clone()).collect();
+        let mut order = WalkOrder::new(&files, MAX_HELD_IMAGES);
+        let paths = |items: Vec<ArrayItem>| -> Vec<String> {
+            items
+                .into_iter()
+                .map(|item| match item {
+                    ArrayItem::Frame(frame) => frame.file.file_path,
+                    ArrayItem::ErrFile(err_file) => err_file.file.file_path,
+                })
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect()
+        };
+        let mut video_frame = frame(0, 1, "b.mp4");
+        video_frame.frame_time = Some(0.0);
+        let mut second_video_frame = frame(0, 1, "b.mp4");
+        second_video_frame.frame_time = Some(1.0);
+
+        // c.jpg waits for a.jpg and b.mp4, another folder doesn't
+        assert!(order.push(ArrayItem::Frame(c)).is_empty());
+        assert_eq!(paths(order.push(ArrayItem::Frame(d))), ["d.jpg"]);
+        // Video frames pass at once
+        assert_eq!(paths(order.push(ArrayItem::Frame(video_frame))), ["b.mp4"]);
+        assert_eq!(paths(order.push(ArrayItem::Frame(a))), ["a.jpg", "c.jpg"]);
+        assert_eq!(
+            paths(order.push(ArrayItem::Frame(second_video_frame))),
+            ["b.mp4"]
+        );
+        assert!(order.finish().is_empty());
+
+        // Images after a file that sent nothing are released at the end
+        let mut order = WalkOrder::new(&files, MAX_HELD_IMAGES);
+        assert!(order
+            .push(ArrayItem::Frame(frame(0, 2, "c.jpg")))
+            .is_empty());
+        assert_eq!(paths(order.finish()), ["c.jpg"]);
+
+        // Past the limit images stop waiting, and the files skipped pass at once
+        let mut order = WalkOrder::new(&files, 0);
+        assert_eq!(
+            paths(order.push(ArrayItem::Frame(frame(0, 2, "c.jpg")))),
+            ["c.jpg"]
+        );
+        assert_eq!(
+            paths(order.push(ArrayItem::Frame(frame(0, 0, "a.jpg")))),
+            ["a.jpg"]
+        );
+        assert!(order.finish().is_empty());
+    }
+
+    #[test]
+    fn test_apply_near_duplicates() {
+        let mut index = BurstIndex::new(NearDuplicateConfig {
+            mode: NearDuplicates::Skip,
+            max_distance: 4,
+            burst_window: 10.0,
+        });
+        let first = burst_frame("a.jpg", 0, None);
+        let second = burst_frame("b.jpg", 3, None);
+        index.insert(&first);
+        let near_duplicate_of = index.insert(&second);
+        index.skipped.push(ExportFrame {
+            near_duplicate_of,
+            ..ExportFrame::from_frame(&second, vec![], HashSet::new())
+        });
+
+        let bbox = crate::utils::Bbox {
+            class: 0,
+            score: 0.9,
+            x1: 1.0,
+            y1: 1.0,
+            x2: 20.0,
+            y2: 20.0,
+        };
+        let mut export_data = vec![ExportFrame::from_frame(
+            &first,
+            vec![bbox],
+            HashSet::from(["Animal".to_string()]),
+        )];
+        apply_near_duplicates(&mut export_data, index);
+        assert_eq!(export_data.len(), 2);
+        assert_eq!(
+            export_data[0].perceptual_hash.as_deref(),
+            Some("0000000000000000")
+        );
+        assert_eq!(export_data[0].near_duplicate_of, None);
+        let copy = &export_data[1];
+        assert_eq!(copy.near_duplicate_of, Some(PathBuf::from("a.jpg")));
+        assert_eq!(copy.label, export_data[0].label);
+        assert_eq!(copy.bboxes.as_ref().unwrap().len(), 1);
+        // The copy keeps its own shoot time
+        assert_ne!(copy.shoot_time, export_data[0].shoot_time);
+    }
+}
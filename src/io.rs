@@ -5,13 +5,15 @@ use anyhow::{Ok, Result};
 use crossbeam_channel::Sender;
 use uuid::Uuid;
 
-use crate::FileItem;
+use crate::utils::FileItem;
 
 fn copy_to_buff(file_path: &PathBuf, buff_path: &Path) -> Result<PathBuf> {
     let mut tmp_name = Uuid::new_v4().to_string();
-    let ext = file_path.extension().unwrap();
-    tmp_name.push_str(".");
-    tmp_name.push_str(ext.to_str().unwrap());
+    // Media kind is sniffed from content, so files without extension are kept as is
+    if let Some(ext) = file_path.extension() {
+        tmp_name.push('.');
+        tmp_name.push_str(&ext.to_string_lossy());
+    }
     let temp_path = buff_path.join(tmp_name);
     fs::copy(file_path, &temp_path)?;
     Ok(temp_path)
@@ -0,0 +1,137 @@
+use crate::stamp::StampMask;
+use crate::utils::PadAlign;
+
+/// Geometry of fitting an image region into the square model input, shared by the image and
+/// video paths. Sizes are rounded like ffmpeg `force_original_aspect_ratio=decrease` and passed
+/// to the ffmpeg `scale` and `pad` filters as is, so both paths place the image at the same pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Letterbox {
+    /// Region of the image fed to the model, `(left, top, width, height)` in image pixels
+    pub region: (u32, u32, u32, u32),
+    pub imgsz: u32,
+    /// Size of the resized region in the model input
+    pub resized: (u32, u32),
+    /// Offset of the resized region in the model input
+    pub pad: (u32, u32),
+}
+
+/// `a * b / c` rounded to nearest like ffmpeg `av_rescale`
+fn rescale(a: u32, b: u32, c: u32) -> u32 {
+    let c = c.max(1) as u64;
+    ((a as u64 * b as u64 + c / 2) / c) as u32
+}
+
+impl Letterbox {
+    pub fn new(width: u32, height: u32, stamp: &StampMask, imgsz: u32, align: PadAlign) -> Self {
+        let (top, bottom) = stamp.pixels(height);
+        let region = (0, top, width, height - top - bottom);
+        let resized = (
+            rescale(imgsz, region.2, region.3).clamp(1, imgsz),
+            rescale(imgsz, region.3, region.2).clamp(1, imgsz),
+        );
+        let pad = match align {
+            PadAlign::Center => ((imgsz - resized.0) / 2, (imgsz - resized.1) / 2),
+            PadAlign::TopLeft => (0, 0),
+        };
+        Letterbox {
+            region,
+            imgsz,
+            resized,
+            pad,
+        }
+    }
+
+    fn scale(&self) -> (f32, f32) {
+        (
+            self.resized.0 as f32 / self.region.2.max(1) as f32,
+            self.resized.1 as f32 / self.region.3.max(1) as f32,
+        )
+    }
+
+    /// Map an image point to the model input
+    #[cfg(test)]
+    pub fn image_to_input(&self, x: f32, y: f32) -> (f32, f32) {
+        let (scale_x, scale_y) = self.scale();
+        (
+            (x - self.region.0 as f32) * scale_x + self.pad.0 as f32,
+            (y - self.region.1 as f32) * scale_y + self.pad.1 as f32,
+        )
+    }
+
+    /// Map a model input point back to the image, clamped to the region fed to the model
+    pub fn input_to_image(&self, x: f32, y: f32) -> (f32, f32) {
+        let (scale_x, scale_y) = self.scale();
+        let (left, top, width, height) = self.region;
+        let x = ((x - self.pad.0 as f32) / scale_x).clamp(0.0, width as f32) + left as f32;
+        let y = ((y - self.pad.1 as f32) / scale_y).clamp(0.0, height as f32) + top as f32;
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stamp::Band;
+
+    fn assert_round_trip(letterbox: &Letterbox) {
+        let (left, top, width, height) = letterbox.region;
+        for (x, y) in [
+            (left, top),
+            (left + width, top + height),
+            (left + width / 3, top + height / 2),
+        ] {
+            let (x, y) = (x as f32, y as f32);
+            let (input_x, input_y) = letterbox.image_to_input(x, y);
+            assert!(input_x >= 0.0 && input_x <= letterbox.imgsz as f32);
+            assert!(input_y >= 0.0 && input_y <= letterbox.imgsz as f32);
+            let (image_x, image_y) = letterbox.input_to_image(input_x, input_y);
+            assert!((image_x - x).abs() < 1e-2, "{} != {}", image_x, x);
+            assert!((image_y - y).abs() < 1e-2, "{} != {}", image_y, y);
+        }
+    }
+
+    #[test]
+    fn test_letterbox_landscape() {
+        let letterbox = Letterbox::new(1920, 1080, &StampMask::default(), 1280, PadAlign::Center);
+        assert_eq!(letterbox.resized, (1280, 720));
+        assert_eq!(letterbox.pad, (0, 280));
+        assert_round_trip(&letterbox);
+    }
+
+    #[test]
+    fn test_letterbox_portrait() {
+        let letterbox = Letterbox::new(1080, 1920, &StampMask::default(), 640, PadAlign::Center);
+        assert_eq!(letterbox.resized, (360, 640));
+        assert_eq!(letterbox.pad, (140, 0));
+        assert_round_trip(&letterbox);
+    }
+
+    #[test]
+    fn test_letterbox_odd_sizes() {
+        // 640 * 333 / 1001 = 212.9, rounded like ffmpeg and padded with the odd pixel at the end
+        let letterbox = Letterbox::new(1001, 333, &StampMask::default(), 640, PadAlign::Center);
+        assert_eq!(letterbox.resized, (640, 213));
+        assert_eq!(letterbox.pad, (0, 213));
+        assert_round_trip(&letterbox);
+
+        let letterbox = Letterbox::new(333, 1001, &StampMask::default(), 640, PadAlign::TopLeft);
+        assert_eq!(letterbox.resized, (213, 640));
+        assert_eq!(letterbox.pad, (0, 0));
+        assert_round_trip(&letterbox);
+    }
+
+    #[test]
+    fn test_letterbox_stamp() {
+        let stamp = StampMask {
+            top: Band::Pixels(20),
+            bottom: Band::Percent(10.0),
+        };
+        let letterbox = Letterbox::new(1000, 1000, &stamp, 640, PadAlign::Center);
+        assert_eq!(letterbox.region, (0, 20, 1000, 880));
+        assert_eq!(letterbox.resized, (640, 563));
+        assert_round_trip(&letterbox);
+        // Boxes on the padding are clamped to the region the model saw
+        assert_eq!(letterbox.input_to_image(0.0, 0.0), (0.0, 20.0));
+        assert_eq!(letterbox.input_to_image(640.0, 640.0), (1000.0, 900.0));
+    }
+}
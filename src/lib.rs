@@ -0,0 +1,77 @@
+use clap::ValueEnum;
+
+pub mod backend;
+pub mod detect;
+pub mod export;
+pub mod hash;
+pub mod io;
+pub mod letterbox;
+pub mod log;
+pub mod media;
+pub mod metadata;
+pub mod stamp;
+pub mod utils;
+
+/// Enum for export formats
+#[derive(ValueEnum, Debug, Clone, Copy)]
+#[value(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    /// JSON format
+    Json,
+
+    /// CSV format
+    Csv,
+}
+
+/// Enum for exported box orientations
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[value(rename_all = "kebab-case")]
+pub enum BoxOrientation {
+    /// Boxes on the image rotated by its EXIF orientation
+    Display,
+
+    /// Boxes on the image as stored in the file
+    Raw,
+}
+
+/// Enum for video frame sampling strategies
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[value(rename_all = "kebab-case")]
+pub enum SampleStrategy {
+    /// Pick frames evenly from all decoded frames
+    Evenly,
+
+    /// Pick one frame every `sample_interval` seconds
+    Interval,
+
+    /// Pick frames evenly from the first `sample_head` seconds
+    Head,
+
+    /// Pick the first frame and frames whose scene change score exceeds `scene_threshold`
+    Scene,
+
+    /// Pick frames with the highest motion energy above `motion_threshold`
+    Motion,
+}
+
+/// Enum for reading video creation times stored without time zone
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[value(rename_all = "kebab-case")]
+pub enum ContainerTime {
+    /// Clock time of the camera, as most trail cameras write it
+    Local,
+
+    /// UTC, as the MP4/MOV format defines it
+    Utc,
+}
+
+/// Enum for handling near-duplicate images in bursts
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[value(rename_all = "kebab-case")]
+pub enum NearDuplicates {
+    /// Detect every image and export the image each near-duplicate resembles
+    Flag,
+
+    /// Detect only the first image of a near-identical group and copy its detections
+    Skip,
+}
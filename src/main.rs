@@ -1,36 +1,93 @@
 use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use anyhow::{Context, Result};
+use chrono::Local;
+use clap::{ArgAction, Parser, Subcommand};
 use crossbeam_channel::{bounded, unbounded};
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use tracing::{error, info, instrument, warn};
 
-use export::ExportFrame;
-use utils::{load_model_config, read_ep_dict, FileItem};
-
-mod detect;
-mod export;
-mod io;
-mod log;
-mod media;
-mod utils;
-
-use crate::detect::{detect_worker, DetectConfig};
-use crate::export::{export, export_worker, parse_export_csv};
-use crate::log::init_logger;
-use crate::media::media_worker;
-use crate::utils::index_files_and_folders;
+use md5rs::backend::MockBackend;
+use md5rs::detect::{backend_worker, detect_worker, DetectConfig};
+use md5rs::export::{
+    export, export_worker, folder_name, move_previous_export, output_path, read_header,
+    remap_export, resume_from_checkpoint, write_header, CheckpointHeader, ExportFrame, OutputNames,
+};
+use md5rs::hash::{
+    apply_near_duplicates, copy_duplicates, find_duplicates, hash_files, near_duplicate_worker,
+    NearDuplicateConfig,
+};
+use md5rs::io;
+use md5rs::log::init_logger;
+use md5rs::media::{media_worker, InputConfig, MediaError, SampleConfig};
+use md5rs::stamp::{load_stamp_profiles, Band, StampMask, StampProfiles};
+use md5rs::utils::{
+    index_file_list, index_files_and_folders, load_model_config, parse_size, read_ep_dict,
+    read_file_list, FileItem, IndexRules, PathPatterns, DEFAULT_EXCLUDES,
+};
+use md5rs::{BoxOrientation, ContainerTime, ExportFormat, NearDuplicates, SampleStrategy};
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// folder to process
-    #[arg(short, long)]
-    folder: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// folder to process. Repeat to process several folders, e.g. on different drives
+    #[arg(short, long, required_unless_present = "file_list")]
+    folder: Vec<String>,
+
+    /// file listing the files to process, one path per line, or `-` to read from stdin.
+    /// Only the listed files are processed, recorded relative to the `--folder` they are under
+    /// or to their own folder
+    #[arg(long)]
+    file_list: Option<String>,
+
+    /// glob of files to process, matched against the file name, or against the path relative
+    /// to the folder if it has a `/`(e.g. `*.jpg`, `site_a/**`). Repeat to process files
+    /// matching any of them
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// glob of files or folders to leave out, matched like `--include`. Repeatable.
+    /// Hidden entries and `Animal`, `Person`, `Vehicle` and `Blank` folders are left out too,
+    /// unless `--no-default-excludes` is given
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// don't leave out hidden entries and organized class folders
+    #[arg(long)]
+    no_default_excludes: bool,
+
+    /// deepest level of files to process, 1 for files directly in the folder
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// smallest file to process, in bytes or with a K, M or G suffix(e.g. 10K)
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// largest file to process, in bytes or with a K, M or G suffix(e.g. 2G)
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// follow symbolic links to files and folders
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// list the files that would be processed, one per line, and exit
+    #[arg(long)]
+    dry_run: bool,
 
     /// path to the model toml file
     #[arg(short, long, default_value = "models/md_v5a_fp16.toml")]
@@ -46,12 +103,61 @@ struct Args {
     #[arg(long, default_value = "3")]
     max_frames: Option<usize>,
 
-    /// decode only I frames in video.
+    /// decode only I frames in video for `evenly` sampling, `--iframe-only false` to decode all.
     /// In short, it helps decode video faster by skip harder frames.
+    /// Other sampling strategies pick frames by time or content and always decode all frames.
     /// Check https://en.wikipedia.org/wiki/Video_compression_picture_types to understand I frames
-    #[arg(long, short, default_value_t = true)]
+    #[arg(
+        long,
+        short,
+        default_value_t = true,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
     iframe_only: bool,
 
+    /// video frame sampling strategy. `max_frames` caps the frames kept by every strategy
+    #[arg(long, value_enum, default_value_t = SampleStrategy::Evenly)]
+    sample: SampleStrategy,
+
+    /// seconds between sampled frames for `interval` sampling
+    #[arg(long, default_value_t = 1.0)]
+    sample_interval: f32,
+
+    /// seconds from video start to sample from for `head` sampling
+    #[arg(long, default_value_t = 5.0)]
+    sample_head: f32,
+
+    /// scene change score threshold(0-1) for `scene` sampling
+    #[arg(long, default_value_t = 0.3)]
+    scene_threshold: f32,
+
+    /// mean pixel difference threshold(0-1) between consecutive frames for `motion` sampling
+    #[arg(long, default_value_t = 0.02)]
+    motion_threshold: f32,
+
+    /// how video creation times without time zone(MP4/MOV `mvhd`) are read: `local` for the
+    /// camera clock time most trail cameras write, `utc` as the format defines it. Exported in
+    /// `time_source` as `container` or `container_utc`
+    #[arg(long, value_enum, default_value_t = ContainerTime::Local)]
+    container_time: ContainerTime,
+
+    /// height of the camera info stamp bar cropped from the image top before detection,
+    /// in pixels(e.g. 40) or percent of image height(e.g. 5%)
+    #[arg(long, default_value = "0")]
+    stamp_top: Band,
+
+    /// height of the camera info stamp bar cropped from the image bottom before detection,
+    /// in pixels(e.g. 40) or percent of image height(e.g. 5%)
+    #[arg(long, default_value = "0")]
+    stamp_bottom: Band,
+
+    /// TOML file of stamp bands per camera model, e.g. `["HC600 HYPERFIRE"]` with `bottom = "6%"`.
+    /// Models not listed use `--stamp-top` and `--stamp-bottom`
+    #[arg(long)]
+    stamp_profiles: Option<String>,
+
     /// batch size. Batch size will increase
     #[arg(short, long, default_value_t = 2)]
     batch: usize,
@@ -60,6 +166,11 @@ struct Args {
     #[arg(short, long, default_value = "2")]
     workers: Vec<usize>,
 
+    /// orientation of exported boxes for images with EXIF orientation.
+    /// `display` is the upright image as viewers show it, `raw` is the image as stored
+    #[arg(long, value_enum, default_value_t = BoxOrientation::Display)]
+    box_orientation: BoxOrientation,
+
     /// NMS IoU threshold
     #[arg(long, default_value_t = 0.45)]
     iou: f32,
@@ -72,12 +183,25 @@ struct Args {
     #[arg(short, long, value_enum, default_value_t = ExportFormat::Json)]
     export: ExportFormat,
 
+    /// export file, or folder to write `result.json`/`result.csv` to if it ends with `/` or
+    /// exists. `{folder}`(first folder name), `{model}`(model name) and `{timestamp}`(start
+    /// time, not allowed with `--incremental`) are replaced, and the export extension is added
+    /// if missing.
+    /// Processed folders are only written to when given here, e.g. `--output <folder>/`
+    #[arg(short, long, default_value = "{folder}_result")]
+    output: String,
+
+    /// export file paths relative to the folder each file was found under(the `root` column),
+    /// so the export stays usable when the folder is mounted elsewhere or opened on another OS
+    #[arg(long)]
+    relative_paths: bool,
+
     /// log level
-    #[arg(long, default_value = "info")]
+    #[arg(long, default_value = "info", global = true)]
     log_level: String,
 
     /// log file
-    #[arg(long, default_value = "md5rs.log")]
+    #[arg(long, default_value = "md5rs.log", global = true)]
     log_file: String,
 
     /// checkpoint interval.
@@ -90,6 +214,42 @@ struct Args {
     #[arg(long)]
     resume_from: Option<String>,
 
+    /// process only files added or changed since the existing export at `--output` and merge
+    /// their results into it. Files are compared by content hash with `--hash`, otherwise by
+    /// size and modification time. The model path and thresholds may differ from the export's
+    #[arg(long, conflicts_with = "resume_from")]
+    incremental: bool,
+
+    /// hash file contents, export the hash and detect files with the same content only once,
+    /// copying the detections of the first file to its duplicates
+    #[arg(long)]
+    hash: bool,
+
+    /// flag near-identical images of a burst(`flag`), or detect only the first image of each
+    /// group and copy its detections to the rest(`skip`)
+    #[arg(long, value_enum)]
+    near_duplicates: Option<NearDuplicates>,
+
+    /// most differing bits(of 64) between perceptual hashes of near-identical images
+    #[arg(long, default_value_t = 4)]
+    near_duplicate_distance: u32,
+
+    /// most seconds between shoot times of images of a burst
+    #[arg(long, default_value_t = 10.0)]
+    burst_window: f32,
+
+    /// resume even if the checkpoint was written with a different model, thresholds or folder
+    #[arg(long)]
+    force_resume: bool,
+
+    /// replace an export of other folders at the output instead of refusing to start
+    #[arg(long)]
+    overwrite: bool,
+
+    /// process files that failed in the checkpoint again instead of keeping their errors
+    #[arg(long)]
+    retry_errors: bool,
+
     /// SSD buffer path. Could help if speed is IO bound when data stored in HDD(make sure it is a SSD path!)
     #[arg(long)]
     buffer_path: Option<String>,
@@ -97,17 +257,30 @@ struct Args {
     /// buffer size. Max files to keep in buffer, adjust on SSD free space
     #[arg(long, default_value_t = 20)]
     buffer_size: usize,
+
+    /// JSON script of boxes returned instead of running the model, for testing
+    #[arg(long, hide = true)]
+    mock_backend: Option<String>,
 }
 
-/// Enum for export formats
-#[derive(ValueEnum, Debug, Clone, Copy)]
-#[value(rename_all = "kebab-case")]
-enum ExportFormat {
-    /// JSON format
-    Json,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Rewrite a path prefix in an existing export and its checkpoint header, e.g. after the
+    /// data moved to another drive
+    Remap {
+        /// export file to rewrite
+        result: PathBuf,
 
-    /// CSV format
-    Csv,
+        /// path prefix to replace, e.g. `D:\camera` or `/mnt/camera`
+        from: String,
+
+        /// path prefix to replace it with
+        to: String,
+
+        /// file to write the rewritten export to, the export itself by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[instrument]
@@ -116,6 +289,22 @@ fn main() -> Result<()> {
 
     let guard = init_logger(args.log_level, args.log_file).expect("Failed to initialize logger");
 
+    if let Some(Command::Remap {
+        result,
+        from,
+        to,
+        output,
+    }) = &args.command
+    {
+        let output = output.as_ref().unwrap_or(result);
+        let changed = remap_export(result, from, to, output)?;
+        info!(
+            "Remapped {} frames from {} to {}, written to {:?}",
+            changed, from, to, output
+        );
+        return Ok(());
+    }
+
     let buffer_path = args.buffer_path.clone();
 
     info!("Cleaning up buffer");
@@ -128,30 +317,209 @@ fn main() -> Result<()> {
 
     if args.checkpoint == 0 {
         error!("Checkpoint should be greater than 0");
-        return Ok(());
+        anyhow::bail!("Checkpoint should be greater than 0");
     }
 
-    let folder_path = std::path::PathBuf::from(&args.folder);
-    let folder_path = std::fs::canonicalize(folder_path).expect("Folder doesn't exist");
+    if args.incremental && args.output.contains("{timestamp}") {
+        error!("--incremental needs an --output without {{timestamp}} to find the existing export");
+        anyhow::bail!(
+            "--incremental needs an --output without {{timestamp}} to find the existing export"
+        );
+    }
+
+    let roots = args
+        .folder
+        .iter()
+        .map(|folder| {
+            std::fs::canonicalize(folder)
+                .with_context(|| format!("Folder {} doesn't exist", folder))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let model_config = load_model_config(&args.model).expect("Failed to load model config");
 
-    let imgsz = model_config.imgsz;
-    let max_frames = args.max_frames;
+    let output_names = OutputNames {
+        folder: match (roots.first(), &args.file_list) {
+            (Some(root), _) => folder_name(root),
+            (None, Some(file_list)) if file_list != "-" => Path::new(file_list)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            (None, _) => "stdin".to_string(),
+        },
+        model: model_config.name.clone(),
+        timestamp: Local::now().format("%Y%m%d-%H%M%S").to_string(),
+    };
+    let result_path = std::path::absolute(output_path(&args.output, &args.export, &output_names)?)?;
+
+    let input_config = InputConfig {
+        imgsz: model_config.imgsz,
+        preprocess: model_config.preprocess,
+    };
+    let sample_config = SampleConfig {
+        strategy: args.sample,
+        max_frames: args.max_frames,
+        interval: args.sample_interval,
+        head: args.sample_head,
+        scene_threshold: args.scene_threshold,
+        motion_threshold: args.motion_threshold,
+        container_time: args.container_time,
+    };
+    let stamp_default = StampMask {
+        top: args.stamp_top,
+        bottom: args.stamp_bottom,
+    };
+    let stamp = match &args.stamp_profiles {
+        Some(path) => StampProfiles::new(stamp_default, load_stamp_profiles(path)?),
+        None => StampProfiles::new(stamp_default, HashMap::new()),
+    };
     let start = Instant::now();
 
-    let mut file_paths = index_files_and_folders(&folder_path);
+    let mut exclude = args.exclude.clone();
+    if !args.no_default_excludes {
+        exclude.extend(DEFAULT_EXCLUDES.map(String::from));
+    }
+    let index_rules = IndexRules {
+        include: PathPatterns::new(&args.include)?,
+        exclude: PathPatterns::new(&exclude)?,
+        max_depth: args.max_depth,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        follow_symlinks: args.follow_symlinks,
+    };
+    let (mut file_paths, skipped_files) = match &args.file_list {
+        Some(file_list) => index_file_list(&read_file_list(file_list)?, &roots),
+        None => index_files_and_folders(&roots, &index_rules),
+    };
+    // The export may be written into a processed folder when requested
+    let skipped_files: Vec<_> = skipped_files
+        .into_iter()
+        .filter(|file| file.file_path != result_path)
+        .collect();
 
     let export_data = Arc::new(Mutex::new(Vec::new()));
 
-    let file_paths = match args.resume_from {
+    let header = CheckpointHeader::new(&model_config, args.conf, args.iou, &roots);
+
+    let resume_from = if args.incremental {
+        if result_path.is_file() {
+            Some(result_path.to_string_lossy().into_owned())
+        } else {
+            info!(
+                "No export to update at {:?}, processing all files",
+                result_path
+            );
+            None
+        }
+    } else {
+        args.resume_from.clone()
+    };
+
+    // `{folder}` names only the first folder, so another run may land on an export of others
+    if resume_from.is_none() && !args.overwrite && result_path.is_file() {
+        if let Some(existing) = read_header(&result_path)? {
+            if existing.roots != header.roots {
+                let message = format!(
+                    "{:?} is an export of other folders {:?}, pass --overwrite to replace it",
+                    result_path, existing.roots
+                );
+                error!("{}", message);
+                anyhow::bail!(message);
+            }
+        }
+    }
+
+    // Incremental runs with hashes compare files with the export by content, which also keeps
+    // files whose modification time changed without their content, e.g. by a copy tool
+    if args.incremental && args.hash {
+        let mut files: Vec<_> = file_paths.into_iter().collect();
+        hash_files(&mut files);
+        file_paths = files.into_iter().collect();
+    }
+
+    let indexed = file_paths.len();
+    let fresh = resume_from.is_none();
+    let file_paths = match resume_from {
         Some(checkpoint_path) => {
-            let all_files =
-                resume_from_checkpoint(&checkpoint_path, &mut file_paths, &export_data)?;
+            let all_files = resume_from_checkpoint(
+                &checkpoint_path,
+                &header,
+                args.force_resume,
+                args.incremental,
+                args.retry_errors,
+                &mut file_paths,
+                &export_data,
+            )?;
             all_files.to_owned()
         }
         None => file_paths,
     };
+    if args.incremental {
+        info!(
+            "Processing {} new or changed files of {} indexed",
+            file_paths.len(),
+            indexed
+        );
+    }
+
+    if args.dry_run {
+        let mut files: Vec<_> = file_paths.iter().collect();
+        files.sort_by_key(|file| (file.folder_id, file.file_id));
+        for file in &files {
+            println!("{}", file.file_path.display());
+        }
+        info!(
+            "Dry run: {} files to process, {} unsupported files",
+            files.len(),
+            skipped_files.len()
+        );
+        return Ok(());
+    }
+
+    info!("Exporting to {:?}", result_path);
+    if let Some(parent) = result_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // The previous export is moved aside with its header, so this run's header is never left
+    // next to the previous results if it stops before its first checkpoint
+    if fresh && result_path.is_file() {
+        let previous = move_previous_export(&result_path)?;
+        warn!("Moved the previous export to {:?}", previous);
+    }
+    write_header(&result_path, &header)?;
+
+    let (file_paths, duplicates) = if args.hash {
+        let mut files: Vec<_> = file_paths.into_iter().collect();
+        hash_files(&mut files);
+        let deduplicated = find_duplicates(files, &export_data.lock().unwrap());
+        (
+            deduplicated.unique.into_iter().collect(),
+            deduplicated.duplicates,
+        )
+    } else {
+        (file_paths, Vec::new())
+    };
+    // Files are decoded roughly in walk order, which keeps few images waiting for the files
+    // before them in near-duplicate detection
+    let mut file_paths: Vec<FileItem> = file_paths.into_iter().collect();
+    file_paths.sort_by_key(|file| (file.folder_id, file.file_id));
+
+    // Report unsupported files, unless the checkpoint already has them
+    {
+        let mut export_data = export_data.lock().unwrap();
+        let exported: HashSet<PathBuf> = export_data
+            .iter()
+            .map(|f: &ExportFrame| f.file.file_path.clone())
+            .collect();
+        let skipped_frames: Vec<ExportFrame> = skipped_files
+            .into_iter()
+            .filter(|f| !exported.contains(&f.file_path))
+            .map(|f| ExportFrame::from_error(f, MediaError::Unsupported.to_string()))
+            .collect();
+        info!("Skipped {} unsupported files", skipped_frames.len());
+        export_data.extend(skipped_frames);
+    }
 
     let mut detect_handles = vec![];
 
@@ -161,20 +529,54 @@ fn main() -> Result<()> {
 
     let (export_q_s, export_q_r) = unbounded();
 
-    let checkpoint_counter = Arc::new(Mutex::new(0 as usize));
+    // Decoded images pass the near-duplicate check on their way to detection
+    let (array_q_s, burst_handle) = match args.near_duplicates {
+        Some(mode) => {
+            let config = NearDuplicateConfig {
+                mode,
+                max_distance: args.near_duplicate_distance,
+                burst_window: args.burst_window,
+            };
+            let (media_q_s, media_q_r) = bounded(args.batch * args.workers.iter().sum::<usize>());
+            let handle = near_duplicate_worker(config, &file_paths, media_q_r, array_q_s);
+            (media_q_s, Some(handle))
+        }
+        None => (array_q_s, None),
+    };
+
+    let mock_backend = match &args.mock_backend {
+        Some(path) => Some(MockBackend::load(path)?),
+        None => None,
+    };
+
+    let checkpoint_counter = Arc::new(Mutex::new(0_usize));
 
     for (i, d) in args.device.iter().enumerate() {
         let detect_config = Arc::new(DetectConfig {
             device: d.clone(),
             model_path: model_config.path.clone(),
             target_size: model_config.imgsz,
+            input: model_config.input,
             class_map: model_config.class_map(),
             iou_thres: args.iou,
             conf_thres: args.conf,
+            box_orientation: args.box_orientation,
             batch_size: args.batch,
             timeout: 50,
         });
-        let ep_dict = read_ep_dict(&d)?;
+        if let Some(mock_backend) = &mock_backend {
+            for _ in 0..args.workers[i] {
+                let detect_handle = backend_worker(
+                    mock_backend.clone(),
+                    Arc::clone(&detect_config),
+                    array_q_r.clone(),
+                    export_q_s.clone(),
+                );
+                detect_handles.push(detect_handle);
+            }
+            continue;
+        }
+        let ep_dict = read_ep_dict(d)?;
         for _ in 0..args.workers[i] {
             let detect_config = Arc::clone(&detect_config);
             let array_q_r = array_q_r.clone();
@@ -188,14 +590,15 @@ fn main() -> Result<()> {
     for _ in 0..4 {
         let export_q_r = export_q_r.clone();
         let export_data = Arc::clone(&export_data);
-        let folder_path = folder_path.clone();
+        let result_path = result_path.clone();
         let checkpoint_counter = Arc::clone(&checkpoint_counter);
         let export_handle = std::thread::spawn(move || {
             export_worker(
                 args.checkpoint,
                 &checkpoint_counter,
                 &args.export,
-                &folder_path,
+                &result_path,
+                args.relative_paths,
                 export_q_r,
                 &export_data,
             );
@@ -230,17 +633,32 @@ fn main() -> Result<()> {
                 .progress_with(pb.clone())
                 .for_each(|file| {
                     let array_q_s = array_q_s.clone();
-                    media_worker(file, imgsz, args.iframe_only, max_frames, array_q_s);
+                    media_worker(
+                        file,
+                        &input_config,
+                        args.iframe_only,
+                        &sample_config,
+                        &stamp,
+                        array_q_s,
+                    );
                 });
             io_handle.join().unwrap();
         }
         None => {
             file_paths
-                .par_iter()
+                .iter()
+                .par_bridge()
                 .progress_with(pb.clone())
                 .for_each(|file| {
                     let array_q_s = array_q_s.clone();
-                    media_worker(file.clone(), imgsz, args.iframe_only, max_frames, array_q_s);
+                    media_worker(
+                        file.clone(),
+                        &input_config,
+                        args.iframe_only,
+                        &sample_config,
+                        &stamp,
+                        array_q_s,
+                    );
                 });
         }
     }
@@ -269,7 +687,16 @@ fn main() -> Result<()> {
         }
     }
 
-    export(&folder_path, export_data, &args.export)?;
+    if let Some(burst_handle) = burst_handle {
+        let index = burst_handle
+            .join()
+            .expect("Error joining near-duplicate worker");
+        apply_near_duplicates(&mut export_data.lock().unwrap(), index);
+    }
+
+    copy_duplicates(&mut export_data.lock().unwrap(), &duplicates);
+
+    export(&result_path, export_data, &args.export, args.relative_paths)?;
 
     let duration = start.elapsed();
     info!("Time elapsed: {:?}", duration);
@@ -290,63 +717,3 @@ fn cleanup_buffer(buffer_path: &Option<String>) -> Result<()> {
     }
     Ok(())
 }
-
-fn resume_from_checkpoint<'a>(
-    checkpoint_path: &str,
-    all_files: &'a mut HashSet<FileItem>,
-    export_data: &Arc<Mutex<Vec<ExportFrame>>>,
-) -> Result<&'a mut HashSet<FileItem>> {
-    let checkpoint = Path::new(checkpoint_path);
-    if !checkpoint.exists() {
-        error!("Checkpoint file does not exist");
-        return Err(anyhow::anyhow!("Checkpoint file does not exist"));
-    }
-    if !checkpoint.is_file() {
-        error!("Checkpoint path is not a file");
-        return Err(anyhow::anyhow!("Checkpoint path is not a file"));
-    }
-    match checkpoint.extension() {
-        Some(ext) => {
-            let ext = ext.to_str().unwrap();
-            if ext != "json" && ext != "csv" {
-                error!("Invalid checkpoint file extension: {}", ext);
-                return Err(anyhow::anyhow!(
-                    "Invalid checkpoint file extension: {}",
-                    ext
-                ));
-            } else {
-                let frames;
-                if ext == "json" {
-                    let json = std::fs::read_to_string(checkpoint)?;
-                    frames = serde_json::from_str(&json)?;
-                } else {
-                    frames = parse_export_csv(checkpoint)?;
-                }
-                let mut file_frame_count = HashMap::new();
-                let mut file_total_frames = HashMap::new();
-                for f in &frames {
-                    let file = &f.file;
-                    let count = file_frame_count.entry(file.clone()).or_insert(0);
-                    *count += 1;
-                    file_total_frames
-                        .entry(file.clone())
-                        .or_insert(f.total_frames);
-
-                    if let Some(total_frames) = file_total_frames.get(&file) {
-                        if let Some(frame_count) = file_frame_count.get(&file) {
-                            if total_frames == frame_count {
-                                all_files.remove(&file);
-                            }
-                        }
-                    }
-                }
-                export_data.lock().unwrap().extend_from_slice(&frames);
-                Ok(all_files)
-            }
-        }
-        None => {
-            error!("Invalid checkpoint file extension");
-            return Err(anyhow::anyhow!("Invalid checkpoint file extension"));
-        }
-    }
-}
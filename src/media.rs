@@ -1,28 +1,41 @@
-use crate::utils::{sample_evenly, FileItem};
+use crate::letterbox::Letterbox;
+use crate::metadata::{read_image_metadata, read_video_metadata, MediaMetadata};
+use crate::stamp::{StampMask, StampProfiles};
+use crate::utils::{
+    detect_media_kind, motion_energy, sample_evenly, FileItem, MediaKind, Preprocess,
+};
+use crate::{ContainerTime, SampleStrategy};
 
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDateTime, TimeDelta, TimeZone};
 use crossbeam_channel::Sender;
-use fast_image_resize::{ResizeAlg, ResizeOptions, Resizer};
+use fast_image_resize::{ResizeOptions, Resizer};
 use ffmpeg_sidecar::command::FfmpegCommand;
-use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
+use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel, OutputVideoFrame};
+use ffmpeg_sidecar::ffprobe::ffprobe_path;
 use ffmpeg_sidecar::iter::FfmpegIterator;
-use image::{DynamicImage, GenericImageView, ImageReader};
+use image::metadata::Orientation;
+use image::{imageops, DynamicImage, ImageError, ImageReader, Rgb, RgbImage};
 use jpeg_decoder::Decoder;
-use ndarray::{s, Array3, Dim};
-use nom_exif::{Exif, ExifIter, ExifTag, MediaParser, MediaSource};
-use nshare::AsNdarray3Mut;
+use ndarray::Array3;
+use nom_exif::{Exif, ExifIter, ExifTag, MediaParser, MediaSource, TrackInfo, TrackInfoTag};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{debug, error, warn};
 
+use std::collections::HashSet;
 use std::fs::{metadata, File};
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
 //define meadia error
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum MediaError {
     #[error("Failed to open file: {0}")]
     IoError(#[from] std::io::Error),
@@ -33,25 +46,118 @@ pub enum MediaError {
     #[error("Failed to decode: {0}")]
     VideoDecodeError(String),
 
+    #[error("Failed to decode frame {0} of {1}")]
+    FrameDecodeError(usize, String),
+
     #[error("Ffmpeg error when decoding {1}: {0}")]
     FfmpegError(String, String),
+
+    #[error("skipped: unsupported")]
+    Unsupported,
 }
 
 pub struct Frame {
     pub file: FileItem,
-    pub data: Array3<f32>,
+    /// Letterboxed RGB pixels in HWC order, converted to the model input type in batches
+    pub data: Array3<u8>,
+    /// Size of the upright image
     pub width: usize,
     pub height: usize,
-    pub padding: (usize, usize),
-    pub ratio: f32,
+    /// Maps the image to `data` and detections back
+    pub letterbox: Letterbox,
     pub frame_index: usize,
     pub total_frames: usize,
+    pub frame_time: Option<f32>,
     pub shoot_time: Option<DateTime<Local>>,
+    pub time_source: Option<TimeSource>,
+    /// Orientation applied to the decoded image to get `data`
+    pub orientation: Orientation,
+    /// Shared by all frames of a video
+    pub metadata: Arc<MediaMetadata>,
+}
+
+impl Frame {
+    /// Absolute time of this frame, the file shoot time plus the frame offset for videos
+    pub fn frame_shoot_time(&self) -> Option<DateTime<Local>> {
+        let offset = TimeDelta::microseconds((self.frame_time.unwrap_or(0.0) * 1e6) as i64);
+        self.shoot_time.map(|shoot_time| shoot_time + offset)
+    }
+}
+
+/// Where the shoot time of a file comes from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeSource {
+    /// EXIF DateTimeOriginal or ModifyDate of an image
+    Exif,
+    /// Creation time in video container metadata as camera clock time, e.g. AVI `IDIT`, or
+    /// MP4/MOV `mvhd` read with `--container-time local`
+    Container,
+    /// Creation time in video container metadata read as UTC with `--container-time utc`
+    ContainerUtc,
+    /// File modification/creation time, which may change when files are copied
+    FileSystem,
+}
+
+impl TimeSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeSource::Exif => "exif",
+            TimeSource::Container => "container",
+            TimeSource::ContainerUtc => "container_utc",
+            TimeSource::FileSystem => "file_system",
+        }
+    }
+}
+
+impl FromStr for TimeSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "exif" => Ok(TimeSource::Exif),
+            "container" => Ok(TimeSource::Container),
+            "container_utc" => Ok(TimeSource::ContainerUtc),
+            "file_system" => Ok(TimeSource::FileSystem),
+            _ => Err(anyhow!("Unknown time source: {}", s)),
+        }
+    }
+}
+
+/// Model input size and the letterbox preprocessing that produces it
+pub struct InputConfig {
+    pub imgsz: usize,
+    pub preprocess: Preprocess,
+}
+
+pub struct SampleConfig {
+    pub strategy: SampleStrategy,
+    pub max_frames: Option<usize>,
+    pub interval: f32,
+    pub head: f32,
+    pub scene_threshold: f32,
+    pub motion_threshold: f32,
+    /// How creation times of MP4/MOV and MKV files are read
+    pub container_time: ContainerTime,
 }
 
 pub struct ErrFile {
     pub file: FileItem,
     pub error: anyhow::Error,
+    /// Frame that failed, `0` of `1` for errors of the whole file
+    pub frame_index: usize,
+    pub total_frames: usize,
+}
+
+impl ErrFile {
+    pub fn new(file: FileItem, error: anyhow::Error) -> Self {
+        ErrFile {
+            file,
+            error,
+            frame_index: 0,
+            total_frames: 1,
+        }
+    }
 }
 
 pub enum ArrayItem {
@@ -61,29 +167,43 @@ pub enum ArrayItem {
 
 pub fn media_worker(
     file: FileItem,
-    imgsz: usize,
+    input: &InputConfig,
     iframe: bool,
-    max_frames: Option<usize>,
+    sample_config: &SampleConfig,
+    stamp: &StampProfiles,
     array_q_s: Sender<ArrayItem>,
 ) {
     let mut parser = MediaParser::new();
     let mut resizer = Resizer::new();
-    if let Some(extension) = file.file_path.extension() {
-        let array_q_s = array_q_s.clone();
-        match extension.to_str().unwrap().to_lowercase().as_str() {
-            "jpg" | "jpeg" | "png" => {
-                process_image(&file, imgsz, &mut parser, &mut resizer, array_q_s).unwrap();
-            }
-            "mp4" | "avi" | "mkv" | "mov" => {
-                process_video(&file, imgsz, iframe, max_frames, array_q_s).unwrap();
-            }
-            _ => (),
+    let array_q_s = array_q_s.clone();
+    match detect_media_kind(&file.tmp_path) {
+        Some(MediaKind::Image) => {
+            process_image(&file, input, stamp, &mut parser, &mut resizer, array_q_s).unwrap();
+        }
+        Some(MediaKind::Video) => {
+            process_video(
+                &file,
+                input,
+                iframe,
+                sample_config,
+                stamp,
+                &mut parser,
+                array_q_s,
+            )
+            .unwrap();
         }
-        if &file.file_path != &file.tmp_path {
-            remove_file_with_retries(&file.tmp_path, 3, Duration::from_secs(1))
-                .expect("Failed to remove file");
+        None => {
+            let err_file =
+                ArrayItem::ErrFile(ErrFile::new(file.clone(), MediaError::Unsupported.into()));
+            array_q_s
+                .send(err_file)
+                .expect("Send unsupported file failed");
         }
     }
+    if file.file_path != file.tmp_path {
+        remove_file_with_retries(&file.tmp_path, 3, Duration::from_secs(1))
+            .expect("Failed to remove file");
+    }
 }
 
 fn remove_file_with_retries(file_path: &PathBuf, max_retries: u32, delay: Duration) -> Result<()> {
@@ -116,65 +236,130 @@ fn remove_file_with_retries(file_path: &PathBuf, max_retries: u32, delay: Durati
 
 fn decode_image(file: &FileItem) -> Result<DynamicImage> {
     let img = match ImageReader::open(file.tmp_path.as_path())
+        .map_err(MediaError::IoError)?
+        .with_guessed_format()
         .map_err(MediaError::IoError)?
         .decode()
     {
         Ok(img) => img,
+        Err(ImageError::Unsupported(_e)) => {
+            // e.g. HEIC/HEIF, which the image crate can't decode
+            debug!("Unsupported by ImageReader. Trying ffmpeg. {:?}", _e);
+            decode_image_ffmpeg(file)?
+        }
         Err(_e) => {
             warn!(
                 "Failed to decode image with ImageReader. Trying jpeg_decoder. {:?}",
                 _e
             );
-            let img_reader = File::open(file.tmp_path.as_path()).map_err(MediaError::IoError)?;
-            let mut decoder = Decoder::new(BufReader::new(img_reader));
-            let pixels = decoder.decode().map_err(MediaError::ImageDecodeError)?;
-            let img = DynamicImage::ImageRgb8(
-                image::ImageBuffer::from_raw(
-                    decoder.info().unwrap().width as u32,
-                    decoder.info().unwrap().height as u32,
-                    pixels,
-                )
-                .unwrap(),
-            );
-            img
+            match decode_jpeg(file) {
+                Ok(img) => img,
+                Err(_e) => {
+                    warn!(
+                        "Failed to decode image with jpeg_decoder. Trying ffmpeg. {:?}",
+                        _e
+                    );
+                    decode_image_ffmpeg(file)?
+                }
+            }
         }
     };
     Ok(img)
 }
 
+fn decode_jpeg(file: &FileItem) -> Result<DynamicImage> {
+    let img_reader = File::open(file.tmp_path.as_path()).map_err(MediaError::IoError)?;
+    let mut decoder = Decoder::new(BufReader::new(img_reader));
+    let pixels = decoder.decode().map_err(MediaError::ImageDecodeError)?;
+    Ok(DynamicImage::ImageRgb8(
+        image::ImageBuffer::from_raw(
+            decoder.info().unwrap().width as u32,
+            decoder.info().unwrap().height as u32,
+            pixels,
+        )
+        .unwrap(),
+    ))
+}
+
+fn decode_image_ffmpeg(file: &FileItem) -> Result<DynamicImage> {
+    let image_path = file.tmp_path.to_string_lossy();
+    // Orientation is applied from EXIF like other images, so ffmpeg must not rotate it
+    let iter = FfmpegCommand::new()
+        .arg("-noautorotate")
+        .input(image_path.as_ref())
+        .args(["-frames:v", "1", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+        .output("-")
+        .spawn()?
+        .iter()?;
+
+    let mut img = None;
+    let mut ffmpeg_error = Vec::new();
+    for event in iter {
+        match event {
+            FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => {
+                ffmpeg_error.push(e);
+            }
+            FfmpegEvent::OutputFrame(frame) if img.is_none() => {
+                img = image::ImageBuffer::from_raw(frame.width, frame.height, frame.data)
+                    .map(DynamicImage::ImageRgb8);
+            }
+            _ => (),
+        }
+    }
+
+    img.ok_or_else(|| {
+        MediaError::FfmpegError(ffmpeg_error.join("; "), image_path.into_owned()).into()
+    })
+}
+
 pub fn process_image(
     file: &FileItem,
-    imgsz: usize,
+    input: &InputConfig,
+    stamp: &StampProfiles,
     parser: &mut MediaParser,
     resizer: &mut Resizer,
     array_q_s: Sender<ArrayItem>,
 ) -> Result<()> {
     let frame_data = match decode_image(file) {
-        Ok(img) => {
-            let (img_array, pad_w, pad_h, ratio) = resize_with_pad(&img, imgsz as u32, resizer)?;
+        Ok(mut img) => {
+            let exif = parse_image_exif(parser, file.tmp_path.as_path()).ok();
+            // Rotate to display orientation so the model sees the image upright
+            let orientation = exif
+                .as_ref()
+                .map(get_image_orientation)
+                .unwrap_or(Orientation::NoTransforms);
+            img.apply_orientation(orientation);
+            let metadata = Arc::new(exif.as_ref().map(read_image_metadata).unwrap_or_default());
+            let stamp = stamp.for_model(metadata.model.as_deref());
+            let letterbox = Letterbox::new(
+                img.width(),
+                img.height(),
+                &stamp,
+                input.imgsz as u32,
+                input.preprocess.align,
+            );
+            let img_array = resize_with_pad(&img, &letterbox, &input.preprocess, resizer)?;
             let shoot_time: Option<DateTime<Local>> =
-                match get_image_date(parser, file.tmp_path.as_path()) {
-                    Ok(shoot_time) => Some(shoot_time),
-                    Err(_e) => None,
-                };
+                exif.as_ref().and_then(|exif| get_image_date(exif).ok());
+            let time_source = shoot_time.map(|_| TimeSource::Exif);
             let frame_data = Frame {
                 data: img_array,
                 file: file.clone(),
                 width: img.width() as usize,
                 height: img.height() as usize,
-                padding: (pad_w, pad_h),
-                ratio,
+                letterbox,
                 frame_index: 0,
                 total_frames: 1,
+                frame_time: None,
                 shoot_time,
+                time_source,
+                orientation,
+                metadata,
             };
 
             ArrayItem::Frame(frame_data)
         }
-        Err(error) => ArrayItem::ErrFile(ErrFile {
-            file: file.clone(),
-            error,
-        }),
+        Err(error) => ArrayItem::ErrFile(ErrFile::new(file.clone(), error)),
     };
     array_q_s.send(frame_data).expect("Send image frame failed");
 
@@ -183,111 +368,527 @@ pub fn process_image(
 
 fn resize_with_pad(
     img: &DynamicImage,
-    imgsz: u32,
+    letterbox: &Letterbox,
+    preprocess: &Preprocess,
     resizer: &mut Resizer,
-) -> Result<(Array3<f32>, usize, usize, f32)> {
-    // Get the dimensions of the original image
-    let (width, height) = img.dimensions();
-    let mut resized_width = imgsz;
-    let mut resized_height = imgsz;
-    let ratio: f32;
-
-    if width > height {
-        ratio = width as f32 / imgsz as f32;
-        resized_height = (height as f32 / ratio) as u32;
-        resized_height = resized_height % 2 + resized_height;
-    } else {
-        ratio = height as f32 / imgsz as f32;
-        resized_width = (width as f32 / ratio) as u32;
-        resized_width = resized_width % 2 + resized_width;
-    }
+) -> Result<Array3<u8>> {
+    let imgsz = letterbox.imgsz;
+    let (left, top, width, height) = letterbox.region;
+    let (resized_width, resized_height) = letterbox.resized;
+    let (pad_width, pad_height) = letterbox.pad;
 
     let mut resized_img = DynamicImage::new(resized_width, resized_height, img.color());
 
-    let resize_option = ResizeOptions::new().resize_alg(ResizeAlg::Nearest);
+    let resize_option = ResizeOptions::new()
+        .resize_alg(preprocess.resize.resize_alg())
+        .crop(left as f64, top as f64, width as f64, height as f64);
 
     resizer
         .resize(img, &mut resized_img, &resize_option)
         .unwrap();
 
-    let mut resized_img = resized_img.to_rgb8();
+    let mut padded_img = RgbImage::from_pixel(imgsz, imgsz, Rgb(preprocess.pad_color));
+    imageops::replace(
+        &mut padded_img,
+        &resized_img.to_rgb8(),
+        pad_width as i64,
+        pad_height as i64,
+    );
+
+    let padded_array =
+        Array3::from_shape_vec((imgsz as usize, imgsz as usize, 3), padded_img.into_raw())?;
+    Ok(padded_array)
+}
 
-    let image_array = resized_img.as_ndarray3_mut().mapv(|x| x as f32 / 255.0);
+/// Per file details shared by every frame decoded from a video
+struct VideoInfo {
+    shoot_time: Option<(DateTime<Local>, TimeSource)>,
+    metadata: Arc<MediaMetadata>,
+    stamp: StampMask,
+    /// Upright size of the video frames
+    size: (u32, u32),
+    letterbox: Letterbox,
+}
 
-    let pad_width = (imgsz - resized_width) / 2;
-    let pad_height = (imgsz - resized_height) / 2;
+/// Max number of planned frames decoded by seeking to each of them.
+/// More frames are decoded in one pass and picked with a select filter.
+const SEEK_FRAMES_LIMIT: usize = 10;
 
-    let mut padded_array = Array3::<f32>::from_elem(Dim([3, imgsz as usize, imgsz as usize]), 0.44);
+/// Seek slightly before the target timestamp so rounding never skips the target frame
+const SEEK_MARGIN: f32 = 0.0005;
 
-    padded_array
-        .slice_mut(s![
-            ..,
-            pad_height as i32..(imgsz - pad_height) as i32,
-            pad_width as i32..(imgsz - pad_width) as i32
+pub fn process_video(
+    file: &FileItem,
+    input: &InputConfig,
+    iframe: bool,
+    sample_config: &SampleConfig,
+    stamp: &StampProfiles,
+    parser: &mut MediaParser,
+    array_q_s: Sender<ArrayItem>,
+) -> Result<()> {
+    let video_path = file.tmp_path.to_string_lossy();
+    let track_info = parse_track_info(parser, file.tmp_path.as_path())
+        .map_err(|e| debug!("No track info in {}: {:?}", video_path, e))
+        .ok();
+    let metadata = track_info
+        .as_ref()
+        .map(read_video_metadata)
+        .unwrap_or_default();
+    let stamp = stamp.for_model(metadata.model.as_deref());
+    let size = match probe_video_size(&video_path) {
+        Ok(size) => size,
+        Err(e) => {
+            // Boxes can't be mapped back to frames of unknown size
+            error!("Failed to probe frame size of {}: {:?}", video_path, e);
+            let error = MediaError::VideoDecodeError(file.file_path.to_string_lossy().into_owned());
+            let frame_data = ArrayItem::ErrFile(ErrFile::new(file.clone(), error.into()));
+            array_q_s.send(frame_data).expect("Send video frame failed");
+            return Ok(());
+        }
+    };
+    let info = VideoInfo {
+        shoot_time: get_video_date(
+            track_info.as_ref(),
+            file.tmp_path.as_path(),
+            sample_config.container_time,
+        )
+        .ok(),
+        letterbox: Letterbox::new(
+            size.0,
+            size.1,
+            &stamp,
+            input.imgsz as u32,
+            input.preprocess.align,
+        ),
+        size,
+        stamp,
+        metadata: Arc::new(metadata),
+    };
+
+    // Key frames are placed by the encoder, so frames picked by time would depend on its GOP
+    let iframe = iframe && sample_config.strategy == SampleStrategy::Evenly;
+    let planned = match probe_frame_times(&video_path, iframe) {
+        Ok(frame_times) => {
+            plan_frames(&frame_times, sample_config).map(|indices| (frame_times, indices))
+        }
+        Err(e) => {
+            debug!("Failed to probe {}: {:?}", video_path, e);
+            None
+        }
+    };
+
+    match planned {
+        Some((frame_times, indices)) if !indices.is_empty() => {
+            decode_planned_frames(
+                file,
+                input,
+                iframe,
+                &frame_times,
+                &indices,
+                &info,
+                array_q_s,
+            )?;
+        }
+        _ => {
+            let ffmpeg_iter = create_ffmpeg_iter(
+                &video_path,
+                input,
+                iframe,
+                None,
+                sampling_filter(sample_config),
+                None,
+                &info,
+            )?;
+            handle_ffmpeg_output(ffmpeg_iter, array_q_s, input, file, sample_config, &info)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Frame size of the first video stream after ffmpeg rotates it to display orientation
+fn probe_video_size(video_path: &str) -> Result<(u32, u32)> {
+    let output = Command::new(ffprobe_path())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height:stream_tags=rotate:stream_side_data=rotation",
+            "-of",
+            "json",
+            video_path,
         ])
-        .assign(&image_array);
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("ffprobe exited with {}", output.status));
+    }
+    parse_video_size(&output.stdout)
+}
 
-    Ok((padded_array, pad_width as usize, pad_height as usize, ratio))
+fn parse_video_size(probe_json: &[u8]) -> Result<(u32, u32)> {
+    let probe: serde_json::Value = serde_json::from_slice(probe_json)?;
+    let stream = probe["streams"].get(0).context("No video stream")?;
+    let width = stream["width"].as_u64().context("No width")? as u32;
+    let height = stream["height"].as_u64().context("No height")? as u32;
+    // Display matrix rotation in newer ffprobe, `rotate` tag in older ones
+    let rotation = stream["side_data_list"]
+        .as_array()
+        .and_then(|side_data| side_data.iter().find_map(|data| data["rotation"].as_i64()))
+        .or_else(|| stream["tags"]["rotate"].as_str()?.parse().ok())
+        .unwrap_or(0);
+    if rotation.rem_euclid(180) == 90 {
+        Ok((height, width))
+    } else {
+        Ok((width, height))
+    }
 }
 
-pub fn process_video(
+/// Probe presentation timestamps of the frames ffmpeg will decode, in seconds from the first frame.
+/// Only packet headers are read, so this is much cheaper than decoding.
+fn probe_frame_times(video_path: &str, iframe: bool) -> Result<Vec<f32>> {
+    let output = Command::new(ffprobe_path())
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "packet=pts_time,flags",
+            "-of",
+            "csv=p=0",
+            video_path,
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(anyhow!("ffprobe exited with {}", output.status));
+    }
+
+    let mut frame_times = Vec::new();
+    let mut start = f32::MAX;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.trim().split(',');
+        let pts = fields
+            .next()
+            .and_then(|pts| pts.parse::<f32>().ok())
+            .context("Packet without pts")?;
+        let flags = fields.next().unwrap_or_default();
+        start = start.min(pts);
+        if !iframe || flags.contains('K') {
+            frame_times.push(pts);
+        }
+    }
+    frame_times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    for t in frame_times.iter_mut() {
+        *t -= start;
+    }
+    Ok(frame_times)
+}
+
+/// Indices of the probed frames to decode.
+/// Returns None if the strategy depends on frame content and can't be planned ahead.
+fn plan_frames(frame_times: &[f32], sample_config: &SampleConfig) -> Option<Vec<usize>> {
+    let candidates: Vec<usize> = match sample_config.strategy {
+        SampleStrategy::Evenly => (0..frame_times.len()).collect(),
+        SampleStrategy::Head => (0..frame_times.len())
+            .filter(|&i| frame_times[i] < sample_config.head)
+            .collect(),
+        SampleStrategy::Interval => {
+            let mut candidates: Vec<usize> = Vec::new();
+            for (i, &t) in frame_times.iter().enumerate() {
+                match candidates.last() {
+                    Some(&last) if t - frame_times[last] < sample_config.interval => {}
+                    _ => candidates.push(i),
+                }
+            }
+            candidates
+        }
+        SampleStrategy::Scene | SampleStrategy::Motion => return None,
+    };
+    let sample_size = sample_config
+        .max_frames
+        .unwrap_or(candidates.len())
+        .min(candidates.len());
+    Some(sample_evenly(&candidates, sample_size))
+}
+
+/// Decode only the planned frames and send them as soon as they are decoded
+fn decode_planned_frames(
     file: &FileItem,
-    imgsz: usize,
+    input: &InputConfig,
     iframe: bool,
-    max_frames: Option<usize>,
-    array_q_s: Sender<ArrayItem>,
+    frame_times: &[f32],
+    indices: &[usize],
+    info: &VideoInfo,
+    s: Sender<ArrayItem>,
 ) -> Result<()> {
     let video_path = file.tmp_path.to_string_lossy();
-    let input = create_ffmpeg_iter(&video_path, imgsz, iframe)?;
+    let file_path = file.file_path.to_string_lossy().into_owned();
+    let total_frames = indices.len();
+
+    let mut ffmpeg_error = Vec::new();
+    let mut sent = HashSet::new();
+    let mut send_frame = |frame: OutputVideoFrame, index: usize| {
+        let frame_data = ArrayItem::Frame(video_frame(
+            file,
+            frame,
+            input,
+            index,
+            total_frames,
+            Some(frame_times[index]),
+            info,
+        ));
+        s.send(frame_data).expect("Send video frame failed");
+        sent.insert(index);
+    };
+
+    if indices.len() <= SEEK_FRAMES_LIMIT {
+        for &index in indices {
+            let seek = (frame_times[index] - SEEK_MARGIN).max(0.0);
+            let ffmpeg_iter =
+                create_ffmpeg_iter(&video_path, input, iframe, Some(seek), None, Some(1), info)?;
+            for event in ffmpeg_iter {
+                match event {
+                    FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => {
+                        ffmpeg_error.push(e);
+                    }
+                    FfmpegEvent::OutputFrame(frame) => send_frame(frame, index),
+                    _ => (),
+                }
+            }
+        }
+    } else {
+        // Selecting every frame is the same as not selecting at all
+        let select = (indices.len() < frame_times.len()).then(|| {
+            let terms: Vec<String> = indices.iter().map(|i| format!("eq(n,{})", i)).collect();
+            format!("select='{}'", terms.join("+"))
+        });
+        let ffmpeg_iter = create_ffmpeg_iter(
+            &video_path,
+            input,
+            iframe,
+            None,
+            select,
+            Some(indices.len()),
+            info,
+        )?;
+        let mut targets = indices.iter();
+        for event in ffmpeg_iter {
+            match event {
+                FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => {
+                    ffmpeg_error.push(e);
+                }
+                FfmpegEvent::OutputFrame(frame) => {
+                    if let Some(&index) = targets.next() {
+                        send_frame(frame, index);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
 
-    handle_ffmpeg_output(input, array_q_s, imgsz, file, max_frames)?;
+    for e in ffmpeg_error {
+        let error = MediaError::FfmpegError(e, file_path.clone());
+        warn!("{:?}", error);
+    }
+
+    if sent.is_empty() {
+        let error = MediaError::VideoDecodeError(file_path).into();
+        error!("{:?}", error);
+        let frame_data = ArrayItem::ErrFile(ErrFile::new(file.clone(), error));
+        s.send(frame_data).expect("Send video frame failed");
+    } else if sent.len() < total_frames {
+        warn!(
+            "Decoded {} of {} planned frames in {}",
+            sent.len(),
+            total_frames,
+            file_path
+        );
+        // Export the missing frames as errors, so resume doesn't take the video for one
+        // interrupted mid-way and `--retry-errors` decodes it again
+        for &index in indices.iter().filter(|index| !sent.contains(index)) {
+            let frame_data = ArrayItem::ErrFile(ErrFile {
+                file: file.clone(),
+                error: MediaError::FrameDecodeError(index, file_path.clone()).into(),
+                frame_index: index,
+                total_frames,
+            });
+            s.send(frame_data).expect("Send video frame failed");
+        }
+    }
 
     Ok(())
 }
 
-fn create_ffmpeg_iter(video_path: &str, imgsz: usize, iframe: bool) -> Result<FfmpegIterator> {
+fn sampling_filter(sample_config: &SampleConfig) -> Option<String> {
+    match sample_config.strategy {
+        SampleStrategy::Interval => Some(format!(
+            "select='isnan(prev_selected_t)+gte(t-prev_selected_t,{})'",
+            sample_config.interval
+        )),
+        SampleStrategy::Scene => Some(format!(
+            "select='eq(n,0)+gt(scene,{})'",
+            sample_config.scene_threshold
+        )),
+        _ => None,
+    }
+}
+
+fn create_ffmpeg_iter(
+    video_path: &str,
+    input: &InputConfig,
+    iframe: bool,
+    seek: Option<f32>,
+    select: Option<String>,
+    max_output: Option<usize>,
+    info: &VideoInfo,
+) -> Result<FfmpegIterator> {
     let mut ffmpeg_command = FfmpegCommand::new();
     if iframe {
         ffmpeg_command.args(["-skip_frame", "nokey"]);
     }
-    let iter = ffmpeg_command
-        .input(video_path)
-        .args(&[
-            "-an",
-            "-vf",
-            &format!(
-                "scale=w={}:h={}:force_original_aspect_ratio=decrease,pad={}:{}:(ow-iw)/2:(oh-ih)/2",
-                imgsz, imgsz, imgsz, imgsz
-            ),
-            "-f",
-            "rawvideo",
-            "-pix_fmt",
-            "rgb24",
-            "-vsync",
-            "vfr",
-        ])
-        .output("-")
-        .spawn()?
-        .iter()?;
+    if let Some(seek) = seek {
+        ffmpeg_command.seek(format!("{:.6}", seek));
+    }
+
+    let mut filters = Vec::new();
+    // Crop first so changing stamp digits never count as scene change or motion
+    filters.extend(info.stamp.ffmpeg_filter(info.size.1));
+    filters.extend(select);
+    // showinfo logs the presentation timestamp of every frame passed to the scaler
+    filters.push("showinfo".to_string());
+    // Scale in RGB to the same letterbox as images so both paths give the model the same pixels
+    let letterbox = &info.letterbox;
+    let [r, g, b] = input.preprocess.pad_color;
+    filters.push("format=rgb24".to_string());
+    filters.push(format!(
+        "scale=w={}:h={}:flags={},pad={}:{}:{}:{}:color=0x{:02x}{:02x}{:02x}",
+        letterbox.resized.0,
+        letterbox.resized.1,
+        input.preprocess.resize.ffmpeg_flags(),
+        letterbox.imgsz,
+        letterbox.imgsz,
+        letterbox.pad.0,
+        letterbox.pad.1,
+        r,
+        g,
+        b
+    ));
+
+    ffmpeg_command.input(video_path).args([
+        "-an",
+        "-vf",
+        &filters.join(","),
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        "rgb24",
+        "-vsync",
+        "vfr",
+    ]);
+    if let Some(max_output) = max_output {
+        ffmpeg_command.frames(max_output as u32);
+    }
+    let iter = ffmpeg_command.output("-").spawn()?.iter()?;
     Ok(iter)
 }
 
+/// Parse `pts_time` from a showinfo filter log line
+fn parse_showinfo_pts(line: &str) -> Option<f32> {
+    if !line.contains("Parsed_showinfo") {
+        return None;
+    }
+    line.split_whitespace()
+        .find_map(|token| token.strip_prefix("pts_time:"))
+        .and_then(|pts| pts.parse().ok())
+}
+
+fn video_frame(
+    file: &FileItem,
+    frame: OutputVideoFrame,
+    input: &InputConfig,
+    frame_index: usize,
+    total_frames: usize,
+    frame_time: Option<f32>,
+    info: &VideoInfo,
+) -> Frame {
+    let imgsz = input.imgsz;
+    let ndarray_frame = Array3::from_shape_vec((imgsz, imgsz, 3), frame.data).unwrap();
+    Frame {
+        data: ndarray_frame,
+        file: file.clone(),
+        width: info.size.0 as usize,
+        height: info.size.1 as usize,
+        letterbox: info.letterbox,
+        frame_index,
+        total_frames,
+        frame_time,
+        shoot_time: info.shoot_time.map(|(time, _)| time),
+        time_source: info.shoot_time.map(|(_, source)| source),
+        // ffmpeg rotates videos to display orientation by default
+        orientation: Orientation::NoTransforms,
+        metadata: info.metadata.clone(),
+    }
+}
+
+/// Decode frames picked by ffmpeg filters or by frame content, then send them once decoding ends
 fn handle_ffmpeg_output(
-    input: FfmpegIterator,
+    ffmpeg_iter: FfmpegIterator,
     s: Sender<ArrayItem>,
-    imgsz: usize,
+    input: &InputConfig,
     file: &FileItem,
-    max_frames: Option<usize>,
+    sample_config: &SampleConfig,
+    info: &VideoInfo,
 ) -> Result<()> {
     let file_path = file.file_path.to_string_lossy().into_owned();
+    let motion = sample_config.strategy == SampleStrategy::Motion;
 
     let mut frames = Vec::new();
+    let mut frame_pts = Vec::new();
     let mut ffmpeg_error = Vec::new();
-    for event in input {
+    // Motion sampling keeps only the previous frame and the best scored frames so far
+    let mut prev_data: Option<Vec<u8>> = None;
+    let mut first_frame = None;
+    let mut scored_frames: Vec<(f32, OutputVideoFrame)> = Vec::new();
+    for event in ffmpeg_iter {
         match event {
             FfmpegEvent::Error(e) | FfmpegEvent::Log(LogLevel::Error, e) => {
                 ffmpeg_error.push(e);
             }
+            FfmpegEvent::Log(LogLevel::Info, line) => {
+                if let Some(pts) = parse_showinfo_pts(&line) {
+                    frame_pts.push(pts);
+                }
+            }
+            FfmpegEvent::OutputFrame(frame) if motion => {
+                let score = prev_data
+                    .as_ref()
+                    .map(|prev| motion_energy(prev, &frame.data))
+                    .unwrap_or(0.0);
+                prev_data = Some(frame.data.clone());
+                if first_frame.is_none() {
+                    first_frame = Some(frame.clone());
+                }
+                if score >= sample_config.motion_threshold {
+                    scored_frames.push((score, frame));
+                    if let Some(max_frames) = sample_config.max_frames {
+                        if scored_frames.len() > max_frames {
+                            let (lowest, _) = scored_frames
+                                .iter()
+                                .enumerate()
+                                .min_by(|(_, a), (_, b)| a.0.partial_cmp(&b.0).unwrap())
+                                .unwrap();
+                            scored_frames.remove(lowest);
+                        }
+                    }
+                }
+            }
             FfmpegEvent::OutputFrame(frame) => {
                 frames.push(frame);
             }
@@ -300,50 +901,42 @@ fn handle_ffmpeg_output(
         warn!("{:?}", error);
     }
 
-    if frames.is_empty() {
+    let sampled_frames = if motion {
+        if scored_frames.is_empty() {
+            // Keep the first frame of a still video so it still gets a result
+            first_frame.into_iter().collect()
+        } else {
+            scored_frames.into_iter().map(|(_, frame)| frame).collect()
+        }
+    } else {
+        let sample_size = sample_config
+            .max_frames
+            .unwrap_or(frames.len())
+            .min(frames.len());
+        sample_evenly(&frames, sample_size)
+    };
+
+    if sampled_frames.is_empty() {
         let error = MediaError::VideoDecodeError(file_path).into();
         error!("{:?}", error);
-        let frame_data = ArrayItem::ErrFile(ErrFile {
-            file: file.clone(),
-            error,
-        });
+        let frame_data = ArrayItem::ErrFile(ErrFile::new(file.clone(), error));
         s.send(frame_data).expect("Send video frame failed");
     } else {
-        let sampled_frames = sample_evenly(&frames, max_frames.unwrap_or(frames.len()));
-
-        let shoot_time: Option<DateTime<Local>> = match get_video_date(&file.tmp_path.as_path()) {
-            Ok(shoot_time) => Some(shoot_time),
-            Err(_e) => None,
-        };
-
-        //calculate ratio and padding
-        let width = frames[0].width as usize;
-        let height = frames[0].height as usize;
-        let pad = (width as i32 - height as i32).abs() / 2;
-        let padding = if width > height {
-            (0, pad as usize)
-        } else {
-            (pad as usize, 0)
-        };
-        let ratio = width.max(height) as f32 / imgsz as f32;
-
         let frames_length = sampled_frames.len();
 
         for f in sampled_frames.into_iter() {
-            let ndarray_frame = Array3::from_shape_vec((imgsz, imgsz, 3), f.data).unwrap();
-            let mut ndarray_frame = ndarray_frame.map(|&x| x as f32 / 255.0);
-            ndarray_frame = ndarray_frame.permuted_axes([2, 0, 1]);
-            let frame_data = ArrayItem::Frame(Frame {
-                data: ndarray_frame,
-                file: file.clone(),
-                width,
-                height,
-                padding,
-                ratio,
-                frame_index: f.frame_num as usize,
-                total_frames: frames_length,
-                shoot_time,
-            });
+            let frame_index = f.frame_num as usize;
+            // Prefer the real presentation timestamp over the frame rate based one
+            let frame_time = frame_pts.get(frame_index).copied().unwrap_or(f.timestamp);
+            let frame_data = ArrayItem::Frame(video_frame(
+                file,
+                f,
+                input,
+                frame_index,
+                frames_length,
+                Some(frame_time),
+                info,
+            ));
             s.send(frame_data).expect("Send video frame failed");
         }
     }
@@ -351,11 +944,14 @@ fn handle_ffmpeg_output(
     Ok(())
 }
 
-fn get_image_date(parser: &mut MediaParser, image: &Path) -> Result<DateTime<Local>> {
+fn parse_image_exif(parser: &mut MediaParser, image: &Path) -> Result<Exif> {
     let ms = MediaSource::file_path(image)?;
 
     let iter: ExifIter = parser.parse(ms)?;
-    let exif: Exif = iter.into();
+    Ok(iter.into())
+}
+
+fn get_image_date(exif: &Exif) -> Result<DateTime<Local>> {
     let shoot_time = exif
         .get(ExifTag::DateTimeOriginal)
         .or_else(|| exif.get(ExifTag::ModifyDate))
@@ -365,7 +961,140 @@ fn get_image_date(parser: &mut MediaParser, image: &Path) -> Result<DateTime<Loc
     Ok(shoot_time)
 }
 
-fn get_video_date(video: &Path) -> Result<DateTime<Local>> {
+fn get_image_orientation(exif: &Exif) -> Orientation {
+    exif.get(ExifTag::Orientation)
+        .and_then(|orientation| orientation.as_u16())
+        .and_then(|orientation| Orientation::from_exif(orientation as u8))
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+fn parse_track_info(parser: &mut MediaParser, video: &Path) -> Result<TrackInfo> {
+    let ms = MediaSource::file_path(video)?;
+    if !ms.has_track() {
+        return Err(anyhow!("No track info"));
+    }
+    Ok(parser.parse(ms)?)
+}
+
+/// Video shoot time from container metadata, falling back to file system time
+fn get_video_date(
+    track_info: Option<&TrackInfo>,
+    video: &Path,
+    container_time: ContainerTime,
+) -> Result<(DateTime<Local>, TimeSource)> {
+    match get_container_date(track_info, video, container_time) {
+        Ok(shoot_time) => Ok(shoot_time),
+        Err(e) => {
+            debug!("No container creation time in {}: {:?}", video.display(), e);
+            Ok((get_file_date(video)?, TimeSource::FileSystem))
+        }
+    }
+}
+
+fn get_container_date(
+    track_info: Option<&TrackInfo>,
+    video: &Path,
+    container_time: ContainerTime,
+) -> Result<(DateTime<Local>, TimeSource)> {
+    let (shoot_time, time_source) = if is_avi(video)? {
+        (get_avi_date(video)?, TimeSource::Container)
+    } else {
+        let create_date = track_info
+            .context("No track info")?
+            .get(TrackInfoTag::CreateDate)
+            .and_then(|date| date.as_time())
+            .context("CreateDate not found")?;
+        read_create_date(create_date, container_time)?
+    };
+    // Unset creation time in mvhd is 0, which is 1904-01-01
+    if shoot_time.year() < 1970 {
+        return Err(anyhow!("Invalid creation time: {}", shoot_time));
+    }
+    Ok((shoot_time, time_source))
+}
+
+/// Creation time parsed from MP4/MOV or MKV metadata. `mvhd` has no time zone and is parsed as
+/// UTC, but most trail cameras write their clock time in it, so `local` takes the time as
+/// written for local time like image EXIF times.
+fn read_create_date(
+    create_date: DateTime<FixedOffset>,
+    container_time: ContainerTime,
+) -> Result<(DateTime<Local>, TimeSource)> {
+    match container_time {
+        ContainerTime::Local => {
+            let shoot_time = Local
+                .from_local_datetime(&create_date.naive_local())
+                .earliest()
+                .context("Invalid local time")?;
+            Ok((shoot_time, TimeSource::Container))
+        }
+        ContainerTime::Utc => Ok((create_date.with_timezone(&Local), TimeSource::ContainerUtc)),
+    }
+}
+
+fn is_avi(video: &Path) -> Result<bool> {
+    let mut header = [0u8; 12];
+    File::open(video)?.read_exact(&mut header)?;
+    Ok(&header[0..4] == b"RIFF" && &header[8..12] == b"AVI ")
+}
+
+/// Read the `IDIT` chunk of an AVI file, which is written as local time by most cameras
+fn get_avi_date(video: &Path) -> Result<DateTime<Local>> {
+    // IDIT lives in the header list, which is at the start of the file
+    let mut buf = Vec::new();
+    File::open(video)?.take(1 << 20).read_to_end(&mut buf)?;
+    let idit = find_riff_chunk(&buf[12.min(buf.len())..], b"IDIT").context("IDIT not found")?;
+    let idit = String::from_utf8_lossy(idit);
+    parse_idit(idit.trim_end_matches(['\0', '\n', '\r', ' ']))
+}
+
+/// Find a chunk by id in RIFF data, descending into LIST chunks
+fn find_riff_chunk<'a>(mut data: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let chunk_id = &data[0..4];
+        let size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let body = &data[8..(8 + size).min(data.len())];
+        if chunk_id == id {
+            return Some(body);
+        }
+        if chunk_id == b"LIST" && body.len() >= 4 {
+            // Frame data is never searched
+            if &body[0..4] == b"movi" {
+                return None;
+            }
+            if let Some(chunk) = find_riff_chunk(&body[4..], id) {
+                return Some(chunk);
+            }
+        }
+        // Chunks are padded to even size
+        let next = 8 + size + size % 2;
+        if next > data.len() {
+            return None;
+        }
+        data = &data[next..];
+    }
+    None
+}
+
+fn parse_idit(idit: &str) -> Result<DateTime<Local>> {
+    let formats = [
+        "%a %b %d %H:%M:%S %Y",
+        "%Y:%m:%d %H:%M:%S",
+        "%Y/%m/%d %H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+    ];
+    let idit = idit.split_whitespace().collect::<Vec<_>>().join(" ");
+    let naive = formats
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&idit, format).ok())
+        .with_context(|| format!("Unknown IDIT format: {}", idit))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .context("Invalid local time")
+}
+
+fn get_file_date(video: &Path) -> Result<DateTime<Local>> {
     let metadata = metadata(video)?;
     #[cfg(target_os = "windows")]
     {
@@ -378,7 +1107,6 @@ fn get_video_date(video: &Path) -> Result<DateTime<Local>> {
     #[cfg(target_os = "linux")]
     #[allow(deprecated)]
     {
-        use chrono::NaiveDateTime;
         use std::os::linux::fs::MetadataExt;
         let m_time: i64 = metadata.st_mtime();
         let c_time: i64 = metadata.st_ctime();
@@ -392,7 +1120,6 @@ fn get_video_date(video: &Path) -> Result<DateTime<Local>> {
 
     #[cfg(target_os = "macos")]
     {
-        use chrono::NaiveDateTime;
         use std::os::unix::fs::MetadataExt;
         let m_time: i64 = metadata.mtime();
         let c_time: i64 = metadata.ctime();
@@ -404,3 +1131,133 @@ fn get_video_date(video: &Path) -> Result<DateTime<Local>> {
         Ok(shoot_time)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::PadAlign;
+
+    #[test]
+    fn test_parse_showinfo_pts() {
+        let line = "[Parsed_showinfo_1 @ 0x5581c0] [info] n:   2 pts:  30720 pts_time:2.4     duration:512";
+        assert_eq!(parse_showinfo_pts(line), Some(2.4));
+        let line = "[info] Stream #0:0: Video: h264, yuv420p, 1920x1080, 25 fps";
+        assert_eq!(parse_showinfo_pts(line), None);
+    }
+
+    fn sample_config(strategy: SampleStrategy, max_frames: Option<usize>) -> SampleConfig {
+        SampleConfig {
+            strategy,
+            max_frames,
+            interval: 1.0,
+            head: 2.0,
+            scene_threshold: 0.3,
+            motion_threshold: 0.02,
+            container_time: ContainerTime::Local,
+        }
+    }
+
+    #[test]
+    fn test_plan_frames() {
+        // 5 seconds at 2 fps
+        let frame_times: Vec<f32> = (0..10).map(|i| i as f32 * 0.5).collect();
+        let plan =
+            |strategy, max_frames| plan_frames(&frame_times, &sample_config(strategy, max_frames));
+        assert_eq!(plan(SampleStrategy::Evenly, Some(3)), Some(vec![0, 3, 6]));
+        assert_eq!(
+            plan(SampleStrategy::Evenly, Some(20)),
+            Some((0..10).collect())
+        );
+        assert_eq!(plan(SampleStrategy::Head, None), Some(vec![0, 1, 2, 3]));
+        assert_eq!(
+            plan(SampleStrategy::Interval, None),
+            Some(vec![0, 2, 4, 6, 8])
+        );
+        assert_eq!(plan(SampleStrategy::Interval, Some(2)), Some(vec![0, 4]));
+        assert_eq!(plan(SampleStrategy::Scene, Some(3)), None);
+        assert_eq!(plan(SampleStrategy::Motion, Some(3)), None);
+    }
+
+    #[test]
+    fn test_find_riff_chunk() {
+        let idit = b"MON JAN 01 12:30:00 2024\n\0";
+        let mut hdrl = b"hdrl".to_vec();
+        hdrl.extend(b"avih");
+        hdrl.extend(4u32.to_le_bytes());
+        hdrl.extend([0u8; 4]);
+        hdrl.extend(b"IDIT");
+        hdrl.extend((idit.len() as u32).to_le_bytes());
+        hdrl.extend(idit);
+        let mut data = b"LIST".to_vec();
+        data.extend((hdrl.len() as u32).to_le_bytes());
+        data.extend(hdrl);
+        assert_eq!(find_riff_chunk(&data, b"IDIT"), Some(&idit[..]));
+        assert_eq!(find_riff_chunk(&data, b"ICRD"), None);
+    }
+
+    #[test]
+    fn test_parse_idit() {
+        let expected = Local
+            .from_local_datetime(
+                &NaiveDateTime::parse_from_str("2024-01-01 12:30:00", "%Y-%m-%d %H:%M:%S").unwrap(),
+            )
+            .unwrap();
+        assert_eq!(parse_idit("MON JAN 01 12:30:00 2024").unwrap(), expected);
+        assert_eq!(parse_idit("Mon Jan  1 12:30:00 2024").unwrap(), expected);
+        assert_eq!(parse_idit("2024:01:01 12:30:00").unwrap(), expected);
+        assert!(parse_idit("not a date").is_err());
+    }
+
+    #[test]
+    fn test_read_create_date() {
+        let create_date = DateTime::parse_from_rfc3339("2024-01-01T12:30:00Z").unwrap();
+        // The camera clock read 12:30, whatever the time zone of this machine
+        assert_eq!(
+            read_create_date(create_date, ContainerTime::Local).unwrap(),
+            (
+                parse_idit("2024:01:01 12:30:00").unwrap(),
+                TimeSource::Container
+            )
+        );
+        let (shoot_time, time_source) = read_create_date(create_date, ContainerTime::Utc).unwrap();
+        assert_eq!(shoot_time, create_date);
+        assert_eq!(time_source, TimeSource::ContainerUtc);
+    }
+
+    #[test]
+    fn test_parse_video_size() {
+        let probe = br#"{"streams": [{"width": 1920, "height": 1080}]}"#;
+        assert_eq!(parse_video_size(probe).unwrap(), (1920, 1080));
+        let probe = br#"{"streams": [{"width": 1920, "height": 1080,
+            "side_data_list": [{"side_data_type": "Display Matrix", "rotation": -90}]}]}"#;
+        assert_eq!(parse_video_size(probe).unwrap(), (1080, 1920));
+        let probe = br#"{"streams": [{"width": 1920, "height": 1080, "tags": {"rotate": "180"}}]}"#;
+        assert_eq!(parse_video_size(probe).unwrap(), (1920, 1080));
+        assert!(parse_video_size(br#"{"streams": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_frame_shoot_time() {
+        let shoot_time = parse_idit("2024:01:01 12:30:00").unwrap();
+        let mut frame = Frame {
+            file: FileItem::new(0, 0, PathBuf::from("video.mp4"), None),
+            data: Array3::zeros((1, 1, 3)),
+            width: 1,
+            height: 1,
+            letterbox: Letterbox::new(1, 1, &StampMask::default(), 1, PadAlign::Center),
+            frame_index: 0,
+            total_frames: 1,
+            frame_time: Some(2.5),
+            shoot_time: Some(shoot_time),
+            time_source: Some(TimeSource::Container),
+            orientation: Orientation::NoTransforms,
+            metadata: Arc::new(MediaMetadata::default()),
+        };
+        assert_eq!(
+            frame.frame_shoot_time(),
+            Some(shoot_time + TimeDelta::milliseconds(2500))
+        );
+        frame.frame_time = None;
+        assert_eq!(frame.frame_shoot_time(), Some(shoot_time));
+    }
+}
@@ -0,0 +1,187 @@
+use nom_exif::{EntryValue, Exif, ExifTag, GPSInfo, LatLng, TrackInfo, TrackInfoTag};
+use serde::{Deserialize, Serialize};
+
+/// EXIF tag code of BodySerialNumber, which `ExifTag` does not name
+const BODY_SERIAL_NUMBER: u16 = 0xa431;
+
+/// Camera and capture details read from image EXIF or video track metadata.
+/// Trap camera fields are only known when the maker note layout is understood.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MediaMetadata {
+    #[serde(default)]
+    pub make: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    #[serde(default)]
+    pub longitude: Option<f64>,
+    #[serde(default)]
+    pub altitude: Option<f64>,
+    /// Ambient temperature in Celsius
+    #[serde(default)]
+    pub temperature: Option<i16>,
+    #[serde(default)]
+    pub moon_phase: Option<String>,
+    #[serde(default)]
+    pub trigger_mode: Option<String>,
+    /// Position of the image in its trigger sequence, starting at 1
+    #[serde(default)]
+    pub sequence_number: Option<u16>,
+    #[serde(default)]
+    pub sequence_length: Option<u16>,
+}
+
+pub fn read_image_metadata(exif: &Exif) -> MediaMetadata {
+    let mut metadata = MediaMetadata {
+        make: exif.get(ExifTag::Make).and_then(entry_text),
+        model: exif.get(ExifTag::Model).and_then(entry_text),
+        serial_number: exif
+            .get_by_ifd_tag_code(0, BODY_SERIAL_NUMBER)
+            .and_then(entry_text),
+        ..Default::default()
+    };
+    if let Ok(Some(gps)) = exif.get_gps_info() {
+        set_gps(&mut metadata, &gps);
+    }
+    if let Some(EntryValue::Undefined(note) | EntryValue::U8Array(note)) =
+        exif.get(ExifTag::MakerNote)
+    {
+        read_maker_note(note, &mut metadata);
+    }
+    metadata
+}
+
+pub fn read_video_metadata(info: &TrackInfo) -> MediaMetadata {
+    let mut metadata = MediaMetadata {
+        make: info.get(TrackInfoTag::Make).and_then(entry_text),
+        model: info.get(TrackInfoTag::Model).and_then(entry_text),
+        ..Default::default()
+    };
+    if let Some(gps) = info.get_gps_info() {
+        set_gps(&mut metadata, gps);
+    }
+    metadata
+}
+
+fn entry_text(value: &EntryValue) -> Option<String> {
+    value
+        .as_str()
+        .map(|s| s.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+fn set_gps(metadata: &mut MediaMetadata, gps: &GPSInfo) {
+    let degrees =
+        |LatLng(d, m, s): &LatLng| d.as_float() + m.as_float() / 60.0 + s.as_float() / 3600.0;
+    let signed = |value: f64, negative: bool| {
+        Some(if negative { -value } else { value }).filter(|v| v.is_finite())
+    };
+    metadata.latitude = signed(degrees(&gps.latitude), gps.latitude_ref == 'S');
+    metadata.longitude = signed(degrees(&gps.longitude), gps.longitude_ref == 'W');
+    metadata.altitude = signed(gps.altitude.as_float(), gps.altitude_ref == 1);
+}
+
+/// Fill trap camera fields from maker notes in a known layout.
+/// Bushnell and Browning do not publish theirs and only keep these details in the
+/// info bar burned into the image, so only Reconyx HyperFire notes are decoded.
+fn read_maker_note(note: &[u8], metadata: &mut MediaMetadata) {
+    if note.starts_with(&[0x01, 0xf1]) {
+        read_reconyx_hyperfire(note, metadata);
+    }
+}
+
+/// Reconyx HyperFire maker note: little endian 16-bit words at fixed offsets
+fn read_reconyx_hyperfire(note: &[u8], metadata: &mut MediaMetadata) {
+    let word = |index: usize| {
+        note.get(index * 2..index * 2 + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+
+    metadata.trigger_mode = note.get(0x0c).and_then(|&mode| match mode {
+        b'C' => Some("CodeLoc Not Entered".to_string()),
+        b'E' => Some("External Sensor".to_string()),
+        b'M' => Some("Motion Detection".to_string()),
+        b'T' => Some("Time Lapse".to_string()),
+        _ => None,
+    });
+    metadata.sequence_number = word(0x07);
+    metadata.sequence_length = word(0x08);
+    metadata.moon_phase = word(0x12).and_then(|phase| {
+        let name = match phase {
+            0 => "New",
+            1 => "New Crescent",
+            2 => "First Quarter",
+            3 => "Waxing Gibbous",
+            4 => "Full",
+            5 => "Waning Gibbous",
+            6 => "Last Quarter",
+            7 => "Old Crescent",
+            _ => return None,
+        };
+        Some(name.to_string())
+    });
+    metadata.temperature = word(0x14).map(|t| t as i16);
+
+    // Serial number is 15 UTF-16 code units, padded with zeros
+    if let Some(serial) = note.get(0x2a..0x2a + 30) {
+        let units: Vec<u16> = serial
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .take_while(|&u| u != 0)
+            .collect();
+        let serial = String::from_utf16_lossy(&units).trim().to_string();
+        if !serial.is_empty() {
+            metadata.serial_number = Some(serial);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hyperfire_note() -> Vec<u8> {
+        let mut note = vec![0u8; 0x58];
+        note[0..2].copy_from_slice(&[0x01, 0xf1]);
+        note[0x0c..0x0e].copy_from_slice(b"M\0");
+        note[0x0e..0x10].copy_from_slice(&2u16.to_le_bytes());
+        note[0x10..0x12].copy_from_slice(&3u16.to_le_bytes());
+        note[0x24..0x26].copy_from_slice(&4u16.to_le_bytes());
+        note[0x26..0x28].copy_from_slice(&25i16.to_le_bytes());
+        note[0x28..0x2a].copy_from_slice(&(-4i16).to_le_bytes());
+        for (i, c) in "H500ABC".encode_utf16().enumerate() {
+            note[0x2a + i * 2..0x2c + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        note
+    }
+
+    #[test]
+    fn test_read_reconyx_hyperfire() {
+        let mut metadata = MediaMetadata::default();
+        read_maker_note(&hyperfire_note(), &mut metadata);
+        assert_eq!(metadata.trigger_mode.as_deref(), Some("Motion Detection"));
+        assert_eq!(metadata.sequence_number, Some(2));
+        assert_eq!(metadata.sequence_length, Some(3));
+        assert_eq!(metadata.moon_phase.as_deref(), Some("Full"));
+        assert_eq!(metadata.temperature, Some(-4));
+        assert_eq!(metadata.serial_number.as_deref(), Some("H500ABC"));
+    }
+
+    #[test]
+    fn test_read_unknown_maker_note() {
+        let mut metadata = MediaMetadata::default();
+        read_maker_note(b"Nikon\0\x02\x10\0\0", &mut metadata);
+        assert_eq!(metadata, MediaMetadata::default());
+
+        // Truncated notes keep whatever fits
+        let mut metadata = MediaMetadata::default();
+        read_maker_note(&hyperfire_note()[..0x20], &mut metadata);
+        assert_eq!(metadata.sequence_number, Some(2));
+        assert_eq!(metadata.temperature, None);
+        assert_eq!(metadata.serial_number, None);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Height of an info stamp band, in pixels or percent of the image height
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "BandValue")]
+pub enum Band {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Default for Band {
+    fn default() -> Self {
+        Band::Pixels(0)
+    }
+}
+
+impl Band {
+    pub fn pixels(&self, size: u32) -> u32 {
+        match *self {
+            Band::Pixels(pixels) => pixels.min(size),
+            Band::Percent(percent) => (size as f32 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+impl FromStr for Band {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f32 = percent.trim().parse()?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(anyhow!("Percent band out of range: {}", s));
+            }
+            Ok(Band::Percent(percent))
+        } else {
+            Ok(Band::Pixels(s.parse()?))
+        }
+    }
+}
+
+impl fmt::Display for Band {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Band::Pixels(pixels) => write!(f, "{}", pixels),
+            Band::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Bands are written as `40` or `"40"` for pixels and `"5%"` for percent in TOML
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BandValue {
+    Pixels(u32),
+    Text(String),
+}
+
+impl TryFrom<BandValue> for Band {
+    type Error = anyhow::Error;
+
+    fn try_from(value: BandValue) -> Result<Self> {
+        match value {
+            BandValue::Pixels(pixels) => Ok(Band::Pixels(pixels)),
+            BandValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// Bands cropped from the top and bottom of the image before detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct StampMask {
+    #[serde(default)]
+    pub top: Band,
+    #[serde(default)]
+    pub bottom: Band,
+}
+
+impl StampMask {
+    /// Top and bottom band heights in pixels. Bands covering the whole image are ignored.
+    pub fn pixels(&self, height: u32) -> (u32, u32) {
+        let top = self.top.pixels(height);
+        let bottom = self.bottom.pixels(height);
+        if top + bottom >= height {
+            (0, 0)
+        } else {
+            (top, bottom)
+        }
+    }
+
+    /// ffmpeg crop filter for frames of `height`, with the bands of `pixels` so boxes are mapped
+    /// back with the same bands. `None` if there is nothing to crop.
+    pub fn ffmpeg_filter(&self, height: u32) -> Option<String> {
+        let (top, bottom) = self.pixels(height);
+        (top + bottom > 0).then(|| format!("crop=iw:{}:0:{}", height - top - bottom, top))
+    }
+}
+
+/// Stamp bands per camera model, with a default for models not listed
+#[derive(Debug, Clone, Default)]
+pub struct StampProfiles {
+    default: StampMask,
+    models: HashMap<String, StampMask>,
+}
+
+impl StampProfiles {
+    pub fn new(default: StampMask, models: HashMap<String, StampMask>) -> Self {
+        let models = models
+            .into_iter()
+            .map(|(model, mask)| (model.trim().to_lowercase(), mask))
+            .collect();
+        StampProfiles { default, models }
+    }
+
+    /// Bands for the EXIF or container camera model, matched case-insensitively
+    pub fn for_model(&self, model: Option<&str>) -> StampMask {
+        model
+            .and_then(|model| self.models.get(&model.trim().to_lowercase()))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Load camera model profiles from a TOML file of `["MODEL"]` tables with `top` and `bottom` bands
+pub fn load_stamp_profiles<P: AsRef<Path>>(path: P) -> Result<HashMap<String, StampMask>> {
+    let toml_str = std::fs::read_to_string(path)?;
+    let models: HashMap<String, StampMask> = toml::from_str(&toml_str)?;
+    Ok(models)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_band() {
+        assert_eq!("32".parse::<Band>().unwrap(), Band::Pixels(32));
+        assert_eq!("5.5%".parse::<Band>().unwrap(), Band::Percent(5.5));
+        assert!("120%".parse::<Band>().is_err());
+        assert!("top".parse::<Band>().is_err());
+        assert_eq!(Band::Pixels(32).pixels(20), 20);
+        assert_eq!(Band::Percent(5.0).pixels(1080), 54);
+        assert_eq!(Band::Percent(5.5).to_string(), "5.5%");
+    }
+
+    #[test]
+    fn test_stamp_mask_pixels() {
+        let mask = StampMask {
+            top: Band::Pixels(10),
+            bottom: Band::Percent(10.0),
+        };
+        assert_eq!(mask.pixels(1000), (10, 100));
+        // Bands covering the whole image would leave nothing to detect
+        assert_eq!(mask.pixels(10), (0, 0));
+        assert_eq!(
+            mask.ffmpeg_filter(1000).as_deref(),
+            Some("crop=iw:890:0:10")
+        );
+        assert_eq!(mask.ffmpeg_filter(10), None);
+        assert_eq!(StampMask::default().ffmpeg_filter(1000), None);
+    }
+
+    #[test]
+    fn test_stamp_profiles() {
+        let models: HashMap<String, StampMask> = toml::from_str(
+            r#"
+            ["HC600 HYPERFIRE"]
+            bottom = "6%"
+
+            ["BTC-8E"]
+            top = 40
+            bottom = "72"
+            "#,
+        )
+        .unwrap();
+        let default = StampMask {
+            top: Band::Pixels(0),
+            bottom: Band::Pixels(100),
+        };
+        let profiles = StampProfiles::new(default, models);
+        assert_eq!(
+            profiles.for_model(Some("hc600 hyperfire")),
+            StampMask {
+                top: Band::Pixels(0),
+                bottom: Band::Percent(6.0),
+            }
+        );
+        assert_eq!(
+            profiles.for_model(Some("BTC-8E ")),
+            StampMask {
+                top: Band::Pixels(40),
+                bottom: Band::Pixels(72),
+            }
+        );
+        assert_eq!(profiles.for_model(Some("Other")), default);
+        assert_eq!(profiles.for_model(None), default);
+    }
+}
@@ -1,13 +1,16 @@
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use fast_image_resize::{FilterType, ResizeAlg};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use image::metadata::Orientation;
 use ort::{ExecutionProvider, Session};
 use serde::{Deserialize, Serialize};
-use tracing::info;
-use walkdir::{DirEntry, WalkDir};
+use tracing::{debug, info, warn};
+use walkdir::WalkDir;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Bbox {
@@ -23,6 +26,38 @@ impl Bbox {
     fn area(&self) -> f32 {
         (self.x2 - self.x1) * (self.y2 - self.y1)
     }
+
+    /// Map a box on an image displayed with `orientation` back to the raw(stored) image.
+    /// `width` and `height` are the displayed image size.
+    pub fn to_raw_orientation(&self, orientation: Orientation, width: f32, height: f32) -> Bbox {
+        let (raw_w, raw_h) = match orientation {
+            Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH => (height, width),
+            _ => (width, height),
+        };
+        let to_raw = |x: f32, y: f32| match orientation {
+            Orientation::NoTransforms => (x, y),
+            Orientation::FlipHorizontal => (raw_w - x, y),
+            Orientation::Rotate180 => (raw_w - x, raw_h - y),
+            Orientation::FlipVertical => (x, raw_h - y),
+            Orientation::Rotate90FlipH => (y, x),
+            Orientation::Rotate90 => (y, raw_h - x),
+            Orientation::Rotate270FlipH => (raw_w - y, raw_h - x),
+            Orientation::Rotate270 => (raw_w - y, x),
+        };
+        let (ax, ay) = to_raw(self.x1, self.y1);
+        let (bx, by) = to_raw(self.x2, self.y2);
+        Bbox {
+            x1: ax.min(bx),
+            y1: ay.min(by),
+            x2: ax.max(bx),
+            y2: ay.max(by),
+            score: self.score,
+            class: self.class,
+        }
+    }
 }
 
 fn iou(box1: &Bbox, box2: &Bbox) -> f32 {
@@ -65,7 +100,7 @@ pub fn nms(boxes: &mut Vec<Bbox>, agnostic: bool, topk: usize, iou_threshold: f3
             std::collections::HashMap::new();
 
         for b in boxes.clone() {
-            class_map.entry(b.class).or_insert_with(Vec::new).push(b);
+            class_map.entry(b.class).or_default().push(b);
         }
 
         for (_, mut class_boxes) in class_map {
@@ -100,13 +135,70 @@ pub fn sample_evenly<T: Clone>(list: &[T], sample_size: usize) -> Vec<T> {
     sampled_elements
 }
 
+/// Mean absolute pixel difference between two frames of the same size, scaled to 0-1
+pub fn motion_energy(prev: &[u8], cur: &[u8]) -> f32 {
+    let len = prev.len().min(cur.len());
+    if len == 0 {
+        return 0.0;
+    }
+    let diff: u64 = prev
+        .iter()
+        .zip(cur)
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum();
+    diff as f32 / (len as f32 * 255.0)
+}
+
+/// Identity of a file that stays the same across runs, unlike the ids numbered in walk order.
+/// Files with the same relative path, size and modification time are taken as the same file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    /// Path relative to the root of the file, with `/` separators on every OS
+    #[serde(default)]
+    pub relative_path: String,
+    #[serde(default)]
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch, kept by most copy tools
+    #[serde(default)]
+    pub mtime: Option<i64>,
+}
+
+impl FileIdentity {
+    pub fn new(root: &Path, path: &Path, metadata: &std::fs::Metadata) -> Self {
+        let relative_path = relative_path(root, path);
+        let mtime = metadata
+            .modified()
+            .ok()
+            .map(|mtime| chrono::DateTime::<chrono::Utc>::from(mtime).timestamp());
+        FileIdentity {
+            relative_path,
+            size: metadata.len(),
+            mtime,
+        }
+    }
+
+    /// Exports written before identities existed have none
+    pub fn is_empty(&self) -> bool {
+        self.relative_path.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Hash)]
 pub struct FileItem {
+    /// Folder and file numbers in walk order, for sorting only as they shift when files are added
     pub folder_id: usize,
     pub file_id: usize,
     pub file_path: PathBuf,
-    #[serde(skip_serializing)]
+    #[serde(skip_serializing, default)]
     pub tmp_path: PathBuf,
+    /// Processed folder the file was found under, which `identity` is relative to
+    #[serde(default)]
+    pub root: PathBuf,
+    #[serde(flatten)]
+    pub identity: FileIdentity,
+    /// Content hash, only computed with `--hash`
+    #[serde(default)]
+    pub hash: Option<String>,
 }
 
 impl Eq for FileItem {}
@@ -123,73 +215,439 @@ impl FileItem {
                 folder_id,
                 file_id,
                 file_path,
-                tmp_path: tmp_path,
+                tmp_path,
+                root: PathBuf::new(),
+                identity: FileIdentity::default(),
+                hash: None,
             },
             None => Self {
                 folder_id,
                 file_id,
                 file_path: file_path.clone(),
                 tmp_path: file_path,
+                root: PathBuf::new(),
+                identity: FileIdentity::default(),
+                hash: None,
             },
         }
     }
+
+    pub fn with_root(mut self, root: &Path) -> Self {
+        self.root = root.to_path_buf();
+        self
+    }
+
+    pub fn with_identity(mut self, identity: FileIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Key matching this file across runs: its root and identity, or its path for exports
+    /// without an identity
+    pub fn key(&self) -> FileKey {
+        if self.identity.is_empty() {
+            FileKey::Path(self.file_path.clone())
+        } else {
+            FileKey::Identity(self.root.clone(), self.identity.clone())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileKey {
+    Identity(PathBuf, FileIdentity),
+    Path(PathBuf),
+}
+
+/// Entries skipped unless `--no-default-excludes` is given: hidden files and folders, and the
+/// class folders files are organized into
+pub const DEFAULT_EXCLUDES: [&str; 5] = [".*", "Animal", "Person", "Vehicle", "Blank"];
+
+/// Glob patterns of entries in the processed folder. Patterns with a `/` match the path
+/// relative to the folder, where `*` stops at `/` and `**` doesn't. Other patterns match the
+/// file or folder name at any depth, like `.gitignore`.
+#[derive(Debug, Clone)]
+pub struct PathPatterns {
+    names: GlobSet,
+    paths: GlobSet,
 }
 
-fn is_skip(entry: &DirEntry) -> bool {
-    let skip_dirs = ["Animal", "Person", "Vehicle", "Blank"];
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| {
-            skip_dirs.contains(&s) || s.starts_with('.') || s == "result.csv" || s == "result.json"
+impl PathPatterns {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| anyhow!("Invalid pattern {:?}: {}", pattern, e))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+        Ok(PathPatterns {
+            names: names.build()?,
+            paths: paths.build()?,
         })
-        .unwrap_or(false)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Whether a pattern matches `relative_path`, which uses `/` separators
+    pub fn matches(&self, relative_path: &str) -> bool {
+        self.names.is_match(file_name(relative_path)) || self.paths.is_match(relative_path)
+    }
+}
+
+/// Which entries of the processed folder are indexed
+#[derive(Debug, Clone)]
+pub struct IndexRules {
+    /// Only files matching one of these are indexed, all files if empty
+    pub include: PathPatterns,
+    /// Files and folders matching one of these are left out with everything below them
+    pub exclude: PathPatterns,
+    /// Deepest level of files, 1 for files directly in the folder
+    pub max_depth: Option<usize>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub follow_symlinks: bool,
+}
+
+impl Default for IndexRules {
+    fn default() -> Self {
+        IndexRules {
+            include: PathPatterns::new::<&str>(&[]).unwrap(),
+            exclude: PathPatterns::new(&DEFAULT_EXCLUDES).unwrap(),
+            max_depth: None,
+            min_size: None,
+            max_size: None,
+            follow_symlinks: false,
+        }
+    }
+}
+
+impl IndexRules {
+    fn is_excluded(&self, relative_path: &str, path: &Path) -> bool {
+        is_output_file(relative_path, path) || self.exclude.matches(relative_path)
+    }
+
+    fn is_included(&self, relative_path: &str, size: u64) -> bool {
+        (self.include.is_empty() || self.include.matches(relative_path))
+            && self.min_size.is_none_or(|min_size| size >= min_size)
+            && self.max_size.is_none_or(|max_size| size <= max_size)
+    }
+}
+
+fn file_name(relative_path: &str) -> &str {
+    relative_path.rsplit('/').next().unwrap_or(relative_path)
+}
+
+/// Path of `path` relative to `root` with `/` separators
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Files md5rs writes, which are never indexed: exports(`result.csv`/`result.json`, or any
+/// CSV or JSON file with a checkpoint header next to it), their hidden checkpoint headers and
+/// temporary files of atomic writes
+fn is_output_file(relative_path: &str, path: &Path) -> bool {
+    let name = file_name(relative_path);
+    matches!(name, "result.csv" | "result.json")
+        || name.starts_with('.') && (name.ends_with(".header.json") || name.ends_with(".tmp"))
+        || (name.ends_with(".csv") || name.ends_with(".json"))
+            && crate::export::header_path(path).is_file()
+}
+
+/// Parse a file size in bytes, with an optional `K`, `M` or `G` suffix for KiB, MiB or GiB
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let unit: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(anyhow!("Unknown size unit: {}", s)),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid size: {}", s))?;
+    Ok((number * unit as f64) as u64)
 }
 
-pub fn index_files_and_folders(folder_path: &PathBuf) -> HashSet<FileItem> {
+/// Index media files under each of `roots` that pass `rules`, numbering folders and files
+/// across roots in order. Files reached again through a repeated or nested root, or a followed
+/// symlink, are indexed once under the first root: a root under one walked before is skipped,
+/// as are folders that are a root walked before.
+/// Returns media files to process and unsupported files, which are reported as skipped.
+pub fn index_files_and_folders(
+    roots: &[PathBuf],
+    rules: &IndexRules,
+) -> (HashSet<FileItem>, Vec<FileItem>) {
     let mut folder_id: usize = 0;
     let mut file_id: usize = 0;
     let mut file_paths = HashSet::new();
+    let mut skipped_paths = Vec::new();
+    let mut indexed = HashSet::new();
+    let mut walked: Vec<PathBuf> = Vec::new();
 
-    for entry in WalkDir::new(folder_path)
-        .sort_by_file_name()
-        .into_iter()
-        .filter_entry(|e| !is_skip(e))
-    {
-        let entry = entry.unwrap();
-        if entry.file_type().is_dir() {
-            folder_id += 1;
-        } else if entry.file_type().is_file() {
-            if is_video_photo(entry.path()) {
-                file_paths.insert(FileItem::new(
-                    folder_id,
-                    file_id,
-                    entry.path().to_path_buf(),
-                    None,
-                ));
-                file_id += 1;
+    for root in roots {
+        let canonical_root = std::fs::canonicalize(root).unwrap_or_else(|_| root.clone());
+        if walked
+            .iter()
+            .any(|walked| canonical_root.starts_with(walked))
+        {
+            continue;
+        }
+        let mut walker = WalkDir::new(root)
+            .sort_by_file_name()
+            .follow_links(rules.follow_symlinks);
+        if let Some(max_depth) = rules.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let is_walked = |path: &Path| {
+            path.strip_prefix(root)
+                .is_ok_and(|relative| walked.contains(&canonical_root.join(relative)))
+        };
+        for entry in walker.into_iter().filter_entry(|e| {
+            e.depth() == 0
+                || !(rules.is_excluded(&relative_path(root, e.path()), e.path())
+                    || e.file_type().is_dir() && is_walked(e.path()))
+        }) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // e.g. a symlink loop or an unreadable folder
+                    warn!("Failed to index: {}", e);
+                    continue;
+                }
+            };
+            if entry.file_type().is_dir() {
+                folder_id += 1;
+            } else if entry.file_type().is_file() {
+                let identity = entry
+                    .metadata()
+                    .map(|metadata| FileIdentity::new(root, entry.path(), &metadata))
+                    .unwrap_or_default();
+                let relative_path = relative_path(root, entry.path());
+                if !rules.is_included(&relative_path, identity.size) {
+                    continue;
+                }
+                // Only a followed symlink can reach a file twice within walked folders
+                if rules.follow_symlinks {
+                    let canonical = std::fs::canonicalize(entry.path())
+                        .unwrap_or_else(|_| entry.path().to_path_buf());
+                    if !indexed.insert(canonical) {
+                        continue;
+                    }
+                }
+                let file = FileItem::new(folder_id, file_id, entry.path().to_path_buf(), None)
+                    .with_root(root)
+                    .with_identity(identity);
+                if is_video_photo(entry.path()) {
+                    file_paths.insert(file);
+                    file_id += 1;
+                } else {
+                    skipped_paths.push(file);
+                }
             }
         }
+        walked.push(canonical_root);
     }
 
-    file_paths
+    (file_paths, number_skipped(skipped_paths, file_id))
 }
 
-fn is_video_photo(path: &Path) -> bool {
-    if let Some(extension) = path.extension() {
-        match extension.to_str().unwrap().to_lowercase().as_str() {
-            "mp4" | "avi" | "mkv" | "mov" => true,
-            "jpg" | "jpeg" | "png" => true,
-            _ => false,
+/// Skipped files are numbered after media files so media file ids stay consecutive
+fn number_skipped(skipped: Vec<FileItem>, first_id: usize) -> Vec<FileItem> {
+    skipped
+        .into_iter()
+        .enumerate()
+        .map(|(i, file)| FileItem {
+            file_id: first_id + i,
+            ..file
+        })
+        .collect()
+}
+
+/// Read a list of files to process, one path per line, from a file or stdin for `-`.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn read_file_list(source: &str) -> Result<Vec<PathBuf>> {
+    let text = if source == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        std::fs::read_to_string(source)
+            .map_err(|e| anyhow!("Failed to read file list {}: {}", source, e))?
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Index the listed files as they are, without walking folders or applying index rules.
+/// Each file is recorded relative to the first of `roots` it is under, or to its own folder.
+/// Files that don't exist are left out with a warning. Folders are numbered in list order.
+pub fn index_file_list(paths: &[PathBuf], roots: &[PathBuf]) -> (HashSet<FileItem>, Vec<FileItem>) {
+    let mut folder_ids: HashMap<PathBuf, usize> = HashMap::new();
+    let mut file_id: usize = 0;
+    let mut file_paths = HashSet::new();
+    let mut skipped_paths = Vec::new();
+    let mut listed = HashSet::new();
+
+    for path in paths {
+        let (path, metadata) = match std::fs::canonicalize(path)
+            .and_then(|path| std::fs::metadata(&path).map(|metadata| (path, metadata)))
+        {
+            Ok((path, metadata)) if metadata.is_file() => (path, metadata),
+            Ok(_) => {
+                warn!("Listed path is not a file: {:?}", path);
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to open listed file {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if !listed.insert(path.clone()) {
+            continue;
+        }
+        let folder = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let root = roots
+            .iter()
+            .find(|root| path.starts_with(root))
+            .unwrap_or(&folder)
+            .clone();
+        let next_folder_id = folder_ids.len() + 1;
+        let folder_id = *folder_ids.entry(folder).or_insert(next_folder_id);
+        let identity = FileIdentity::new(&root, &path, &metadata);
+        let file = FileItem::new(folder_id, file_id, path.clone(), None)
+            .with_root(&root)
+            .with_identity(identity);
+        if is_video_photo(&path) {
+            file_paths.insert(file);
+            file_id += 1;
+        } else {
+            skipped_paths.push(file);
         }
+    }
+
+    (file_paths, number_skipped(skipped_paths, file_id))
+}
+
+/// Image extensions to process. Formats the image crate can't decode, like HEIC, go through ffmpeg
+pub const IMAGE_EXTENSIONS: [&str; 9] = [
+    "jpg", "jpeg", "png", "heic", "heif", "tif", "tiff", "webp", "bmp",
+];
+
+/// Video extensions to process
+pub const VIDEO_EXTENSIONS: [&str; 15] = [
+    "mp4", "avi", "mkv", "mov", "m4v", "3gp", "mts", "m2ts", "ts", "wmv", "asf", "mpg", "mpeg",
+    "flv", "webm",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaKind {
+    Image,
+    Video,
+}
+
+/// Media kind by file extension
+pub fn media_kind(path: &Path) -> Option<MediaKind> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        Some(MediaKind::Image)
+    } else if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+        Some(MediaKind::Video)
     } else {
-        false
+        None
+    }
+}
+
+/// Media kind by file content, falling back to file extension if the content is not recognised
+pub fn detect_media_kind(path: &Path) -> Option<MediaKind> {
+    sniff_media_kind(path).or_else(|| media_kind(path))
+}
+
+fn sniff_media_kind(path: &Path) -> Option<MediaKind> {
+    let mut header = Vec::with_capacity(512);
+    File::open(path)
+        .ok()?
+        .take(512)
+        .read_to_end(&mut header)
+        .ok()?;
+    sniff_header(&header)
+}
+
+/// Recognise media by magic bytes
+fn sniff_header(h: &[u8]) -> Option<MediaKind> {
+    let at = |offset: usize, magic: &[u8]| h.get(offset..offset + magic.len()) == Some(magic);
+
+    // JPEG, PNG, TIFF(little/big endian)
+    if at(0, b"\xFF\xD8\xFF") || at(0, b"\x89PNG\r\n\x1a\n") || at(0, b"II*\0") || at(0, b"MM\0*") {
+        return Some(MediaKind::Image);
+    }
+    // BMP, checked with its zero reserved fields as the magic is short
+    if at(0, b"BM") && at(6, &[0, 0, 0, 0]) {
+        return Some(MediaKind::Image);
+    }
+    if at(0, b"RIFF") {
+        if at(8, b"WEBP") {
+            return Some(MediaKind::Image);
+        }
+        if at(8, b"AVI ") {
+            return Some(MediaKind::Video);
+        }
     }
+    // ISO base media: HEIF brands are images, others(mp4, mov, m4v, 3gp..) are videos
+    if at(4, b"ftyp") {
+        let heif_brands: [&[u8]; 5] = [b"heic", b"heix", b"heim", b"heis", b"mif1"];
+        if heif_brands.iter().any(|brand| at(8, brand)) {
+            return Some(MediaKind::Image);
+        }
+        return Some(MediaKind::Video);
+    }
+    // Matroska/WebM, ASF/WMV, MPEG program stream, FLV
+    if at(0, b"\x1A\x45\xDF\xA3")
+        || at(0, b"\x30\x26\xB2\x75\x8E\x66\xCF\x11")
+        || at(0, b"\x00\x00\x01\xBA")
+        || at(0, b"FLV\x01")
+    {
+        return Some(MediaKind::Video);
+    }
+    // MPEG transport stream has a sync byte every 188 bytes, M2TS(MTS) adds a 4 bytes timecode
+    if (at(0, b"G") && at(188, b"G")) || (at(4, b"G") && at(196, b"G")) {
+        return Some(MediaKind::Video);
+    }
+    None
+}
+
+fn is_video_photo(path: &Path) -> bool {
+    detect_media_kind(path).is_some()
 }
 
 // EP availability check
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Ep {
     CoreML,
     TensorRT,
@@ -199,20 +657,6 @@ pub enum Ep {
     Cpu,
 }
 
-impl PartialEq for Ep {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Ep::CoreML, Ep::CoreML) => true,
-            (Ep::TensorRT, Ep::TensorRT) => true,
-            (Ep::CUDA, Ep::CUDA) => true,
-            (Ep::OpenVINO, Ep::OpenVINO) => true,
-            (Ep::DirectML, Ep::DirectML) => true,
-            (Ep::Cpu, Ep::Cpu) => true,
-            _ => false,
-        }
-    }
-}
-
 #[derive(Serialize, Deserialize, Clone)]
 pub struct EpInfo {
     pub ep: Ep,
@@ -417,23 +861,154 @@ pub fn read_ep_dict(device: &str) -> Result<EpDict> {
     }
     let json_file_name = std::format!("epinfo_{}.json", device);
     let json_path = Path::new(&json_file_name);
-    let ep_dict: EpDict;
-    if json_path.exists() {
+    let ep_dict: EpDict = if json_path.exists() {
         let json = std::fs::read_to_string(json_path)?;
-        ep_dict = serde_json::from_str(&json)?;
+        serde_json::from_str(&json)?
     } else {
         check_ep_availability(device)?;
-        ep_dict = read_ep_dict(device)?;
-    }
+        read_ep_dict(device)?
+    };
     Ok(ep_dict)
 }
 
+/// Resize filter used to scale images and video frames to the model input
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+    Lanczos,
+}
+
+impl ResizeFilter {
+    pub fn resize_alg(&self) -> ResizeAlg {
+        match self {
+            ResizeFilter::Nearest => ResizeAlg::Nearest,
+            ResizeFilter::Bilinear => ResizeAlg::Convolution(FilterType::Bilinear),
+            ResizeFilter::Lanczos => ResizeAlg::Convolution(FilterType::Lanczos3),
+        }
+    }
+
+    /// ffmpeg scale filter `flags`
+    pub fn ffmpeg_flags(&self) -> &'static str {
+        match self {
+            ResizeFilter::Nearest => "neighbor",
+            ResizeFilter::Bilinear => "bilinear",
+            ResizeFilter::Lanczos => "lanczos",
+        }
+    }
+}
+
+/// Where the resized image is placed in the padded model input
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PadAlign {
+    #[default]
+    Center,
+    TopLeft,
+}
+
+/// Letterbox preprocessing, set in the `[preprocess]` table of the model toml to match training
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Preprocess {
+    #[serde(default)]
+    pub resize: ResizeFilter,
+    /// RGB colour of the padding
+    #[serde(default = "default_pad_color")]
+    pub pad_color: [u8; 3],
+    #[serde(default)]
+    pub align: PadAlign,
+}
+
+/// YOLO letterbox grey
+fn default_pad_color() -> [u8; 3] {
+    [114, 114, 114]
+}
+
+impl Default for Preprocess {
+    fn default() -> Self {
+        Preprocess {
+            resize: ResizeFilter::default(),
+            pad_color: default_pad_color(),
+            align: PadAlign::default(),
+        }
+    }
+}
+
+/// Element type of the model input tensor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputDtype {
+    #[default]
+    Float32,
+    Float16,
+    Uint8,
+}
+
+/// Axis order of the model input tensor
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputLayout {
+    #[default]
+    Nchw,
+    Nhwc,
+}
+
+/// Model input tensor, set in the `[input]` table of the model toml
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct InputTensor {
+    #[serde(default)]
+    pub dtype: InputDtype,
+    #[serde(default)]
+    pub layout: InputLayout,
+    /// Scale pixels to 0-1 for float inputs. Disable for models with normalization baked in
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+}
+
+fn default_normalize() -> bool {
+    true
+}
+
+impl Default for InputTensor {
+    fn default() -> Self {
+        InputTensor {
+            dtype: InputDtype::default(),
+            layout: InputLayout::default(),
+            normalize: default_normalize(),
+        }
+    }
+}
+
+impl InputTensor {
+    pub fn shape(&self, batch: usize, imgsz: usize) -> [usize; 4] {
+        match self.layout {
+            InputLayout::Nchw => [batch, 3, imgsz, imgsz],
+            InputLayout::Nhwc => [batch, imgsz, imgsz, 3],
+        }
+    }
+
+    /// Factor applied to `u8` pixel values before they are fed to the model
+    pub fn scale(&self) -> f32 {
+        if self.normalize {
+            1.0 / 255.0
+        } else {
+            1.0
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ModelConfig {
     pub name: String,
     pub path: PathBuf,
     pub imgsz: usize,
     pub classes: BTreeSet<String>,
+    #[serde(default)]
+    pub preprocess: Preprocess,
+    #[serde(default)]
+    pub input: InputTensor,
 }
 
 impl PartialEq for ModelConfig {
@@ -442,6 +1017,8 @@ impl PartialEq for ModelConfig {
             && self.path == other.path
             && self.imgsz == other.imgsz
             && self.classes == other.classes
+            && self.preprocess == other.preprocess
+            && self.input == other.input
     }
 }
 
@@ -475,6 +1052,371 @@ pub fn load_model_config<P: AsRef<Path>>(config: P) -> Result<ModelConfig> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_file_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        std::fs::create_dir(root.join("site_a")).unwrap();
+        let png = |path: PathBuf| image::RgbImage::new(2, 2).save(path).unwrap();
+        png(root.join("site_a").join("b.png"));
+        std::fs::write(root.join("notes.txt"), "not media").unwrap();
+
+        let (files, skipped) =
+            index_files_and_folders(std::slice::from_ref(&root), &IndexRules::default());
+        let file = files.iter().next().unwrap();
+        assert_eq!(file.identity.relative_path, "site_a/b.png");
+        assert_eq!(
+            file.identity.size,
+            std::fs::metadata(&file.file_path).unwrap().len()
+        );
+        assert!(file.identity.mtime.is_some());
+        assert_eq!(
+            file.key(),
+            FileKey::Identity(root.clone(), file.identity.clone())
+        );
+        assert_eq!(file.root, root);
+        assert_eq!(
+            FileIdentity::new(
+                &root,
+                &file.file_path,
+                &std::fs::metadata(&file.file_path).unwrap()
+            ),
+            file.identity
+        );
+        assert_eq!(skipped[0].identity.relative_path, "notes.txt");
+
+        // Adding a file sorted before shifts the ids but not the identity
+        png(root.join("site_a").join("a.png"));
+        let (files, _) =
+            index_files_and_folders(std::slice::from_ref(&root), &IndexRules::default());
+        let moved = files
+            .iter()
+            .find(|f| f.identity.relative_path == "site_a/b.png")
+            .unwrap();
+        assert_ne!(moved.file_id, file.file_id);
+        assert_eq!(moved.key(), file.key());
+
+        let legacy = FileItem::new(0, 0, PathBuf::from("/data/a.jpg"), None);
+        assert_eq!(legacy.key(), FileKey::Path(PathBuf::from("/data/a.jpg")));
+    }
+
+    #[test]
+    fn test_index_roots_and_file_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let (drive_a, drive_b) = (dir.path().join("drive_a"), dir.path().join("drive_b"));
+        let png = |path: PathBuf| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            image::RgbImage::new(2, 2).save(path).unwrap();
+        };
+        // The same relative path on both drives
+        png(drive_a.join("DCIM").join("a.png"));
+        png(drive_b.join("DCIM").join("a.png"));
+        png(dir.path().join("loose").join("b.png"));
+        std::fs::write(drive_b.join("notes.txt"), "not media").unwrap();
+
+        let roots = [drive_a.clone(), drive_b.clone()];
+        let (files, skipped) = index_files_and_folders(&roots, &IndexRules::default());
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort_by_key(|f| f.file_id);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].root, drive_a);
+        assert_eq!(files[1].root, drive_b);
+        assert_eq!(
+            files[0].identity.relative_path,
+            files[1].identity.relative_path
+        );
+        assert_ne!(files[0].key(), files[1].key());
+        assert!(files[0].folder_id < files[1].folder_id);
+        assert_eq!(skipped[0].file_id, 2);
+
+        let list = dir.path().join("list.txt");
+        std::fs::write(
+            &list,
+            format!(
+                "# files to process\n{}\n\n{}\n{}\n{}\n{}\n",
+                drive_b.join("DCIM").join("a.png").display(),
+                dir.path().join("loose").join("b.png").display(),
+                drive_b.join("notes.txt").display(),
+                drive_b.join("DCIM").join("a.png").display(),
+                dir.path().join("missing.png").display(),
+            ),
+        )
+        .unwrap();
+        let paths = read_file_list(list.to_str().unwrap()).unwrap();
+        assert_eq!(paths.len(), 5);
+        let (files, skipped) = index_file_list(&paths, &roots);
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort_by_key(|f| f.file_id);
+        let relative: Vec<_> = files
+            .iter()
+            .map(|f| (f.root.clone(), f.identity.relative_path.as_str()))
+            .collect();
+        assert_eq!(
+            relative,
+            [
+                (drive_b.clone(), "DCIM/a.png"),
+                (dir.path().join("loose"), "b.png")
+            ]
+        );
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].identity.relative_path, "notes.txt");
+        assert!(read_file_list("missing.txt").is_err());
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        let patterns = PathPatterns::new(&["*.jpg", "site_a/*", "/backup/**"]).unwrap();
+        assert!(patterns.matches("a.jpg"));
+        assert!(patterns.matches("site_b/deep/a.jpg"));
+        assert!(patterns.matches("site_a/b.png"));
+        // `*` stops at `/` in path patterns
+        assert!(!patterns.matches("site_a/deep/b.png"));
+        assert!(patterns.matches("backup/deep/b.png"));
+        assert!(!patterns.matches("site_b/backup/b.png"));
+        assert!(PathPatterns::new::<&str>(&[]).unwrap().is_empty());
+        assert!(PathPatterns::new(&["[a"]).is_err());
+
+        let defaults = PathPatterns::new(&DEFAULT_EXCLUDES).unwrap();
+        assert!(defaults.matches("site_a/Blank"));
+        assert!(defaults.matches(".thumbnails"));
+        assert!(!defaults.matches("Blank Creek"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("10K").unwrap(), 10 << 10);
+        assert_eq!(parse_size("1.5 MiB").unwrap(), 3 << 19);
+        assert_eq!(parse_size("2g").unwrap(), 2 << 30);
+        assert!(parse_size("2T").is_err());
+        assert!(parse_size("big").is_err());
+    }
+
+    #[test]
+    fn test_index_overlapping_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("drive");
+        std::fs::create_dir_all(root.join("DCIM")).unwrap();
+        image::RgbImage::new(2, 2)
+            .save(root.join("DCIM").join("a.png"))
+            .unwrap();
+        std::fs::write(root.join("DCIM").join("notes.txt"), "not media").unwrap();
+
+        let roots = [root.clone(), root.join("DCIM"), root.clone()];
+        let (files, skipped) = index_files_and_folders(&roots, &IndexRules::default());
+        assert_eq!(files.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        // The first root wins
+        let file = files.iter().next().unwrap();
+        assert_eq!(file.root, root);
+        assert_eq!(file.identity.relative_path, "DCIM/a.png");
+
+        // A folder walked before is skipped under a later root
+        let roots = [root.join("DCIM"), root.clone()];
+        let (files, skipped) = index_files_and_folders(&roots, &IndexRules::default());
+        assert_eq!(files.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        let file = files.iter().next().unwrap();
+        assert_eq!(file.root, root.join("DCIM"));
+        assert_eq!(file.identity.relative_path, "a.png");
+    }
+
+    #[test]
+    fn test_index_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        let png = |path: &str, size: u32| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            image::RgbImage::new(size, size).save(path).unwrap();
+        };
+        png("a.png", 2);
+        png("large.png", 64);
+        png("Blank Creek/b.png", 2);
+        png("Blank Creek/deep/c.png", 2);
+        png("site/Blank/d.png", 2);
+        png(".thumbnails/e.png", 2);
+        std::fs::write(root.join("result.csv"), "").unwrap();
+        std::fs::write(root.join(".result.csv.header.json"), "{}").unwrap();
+        // A timestamped export is recognized by its header, other CSV files are indexed
+        std::fs::write(root.join("site_20240501-120000.json"), "[]").unwrap();
+        std::fs::write(root.join(".site_20240501-120000.json.header.json"), "{}").unwrap();
+        std::fs::write(root.join("notes.csv"), "").unwrap();
+        let indexed = |rules: &IndexRules| {
+            let (files, skipped) = index_files_and_folders(std::slice::from_ref(&root), rules);
+            let mut paths: Vec<_> = files
+                .iter()
+                .chain(&skipped)
+                .map(|f| f.identity.relative_path.clone())
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(
+            indexed(&IndexRules::default()),
+            [
+                "Blank Creek/b.png",
+                "Blank Creek/deep/c.png",
+                "a.png",
+                "large.png",
+                "notes.csv"
+            ]
+        );
+
+        // Output files are never indexed, even without the default excludes
+        let all = IndexRules {
+            exclude: PathPatterns::new::<&str>(&[]).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(indexed(&all).len(), 7);
+
+        let rules = IndexRules {
+            include: PathPatterns::new(&["*.png"]).unwrap(),
+            exclude: PathPatterns::new(&["deep", "large.png", ".*"]).unwrap(),
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(indexed(&rules), ["Blank Creek/b.png", "a.png"]);
+
+        let large = std::fs::metadata(root.join("large.png")).unwrap().len();
+        let rules = IndexRules {
+            min_size: Some(large),
+            ..Default::default()
+        };
+        assert_eq!(indexed(&rules), ["large.png"]);
+        let rules = IndexRules {
+            max_size: Some(large - 1),
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(indexed(&rules), ["a.png", "notes.csv"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_index_follow_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        let other = dir.path().join("other");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        image::RgbImage::new(2, 2)
+            .save(other.join("a.png"))
+            .unwrap();
+        std::os::unix::fs::symlink(&other, root.join("linked")).unwrap();
+        // A loop is reported and skipped
+        std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+
+        let (files, _) =
+            index_files_and_folders(std::slice::from_ref(&root), &IndexRules::default());
+        assert!(files.is_empty());
+        let rules = IndexRules {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let (files, _) = index_files_and_folders(std::slice::from_ref(&root), &rules);
+        let paths: Vec<_> = files
+            .iter()
+            .map(|f| f.identity.relative_path.as_str())
+            .collect();
+        assert_eq!(paths, ["linked/a.png"]);
+    }
+
+    #[test]
+    fn test_bbox_to_raw_orientation() {
+        // 40x20 raw image with a box at its top left corner
+        let raw = Bbox {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 10.0,
+            y2: 5.0,
+            score: 0.9,
+            class: 0,
+        };
+        // The same box on the 20x40 image displayed with 90 degrees clockwise rotation
+        let rotated = Bbox {
+            x1: 15.0,
+            y1: 0.0,
+            x2: 20.0,
+            y2: 10.0,
+            score: 0.9,
+            class: 0,
+        };
+        let mapped = rotated.to_raw_orientation(Orientation::Rotate90, 20.0, 40.0);
+        assert_eq!(
+            (mapped.x1, mapped.y1, mapped.x2, mapped.y2),
+            (raw.x1, raw.y1, raw.x2, raw.y2)
+        );
+        // Displayed 180 degrees rotated, the box is at the bottom right corner
+        let rotated = Bbox {
+            x1: 30.0,
+            y1: 15.0,
+            x2: 40.0,
+            y2: 20.0,
+            score: 0.9,
+            class: 0,
+        };
+        let mapped = rotated.to_raw_orientation(Orientation::Rotate180, 40.0, 20.0);
+        assert_eq!(
+            (mapped.x1, mapped.y1, mapped.x2, mapped.y2),
+            (raw.x1, raw.y1, raw.x2, raw.y2)
+        );
+        let mapped = raw.to_raw_orientation(Orientation::NoTransforms, 40.0, 20.0);
+        assert_eq!(
+            (mapped.x1, mapped.y1, mapped.x2, mapped.y2),
+            (raw.x1, raw.y1, raw.x2, raw.y2)
+        );
+    }
+
+    #[test]
+    fn test_media_kind() {
+        assert_eq!(
+            media_kind(Path::new("a/IMG_0001.JPG")),
+            Some(MediaKind::Image)
+        );
+        assert_eq!(
+            media_kind(Path::new("a/IMG_0001.heic")),
+            Some(MediaKind::Image)
+        );
+        assert_eq!(media_kind(Path::new("a/scan.TIFF")), Some(MediaKind::Image));
+        assert_eq!(
+            media_kind(Path::new("a/VID_0001.MOV")),
+            Some(MediaKind::Video)
+        );
+        assert_eq!(media_kind(Path::new("a/notes.txt")), None);
+        assert_eq!(media_kind(Path::new("a/README")), None);
+    }
+
+    #[test]
+    fn test_sniff_header() {
+        assert_eq!(sniff_header(b"\xFF\xD8\xFF\xE1"), Some(MediaKind::Image));
+        assert_eq!(sniff_header(b"\0\0\0\x18ftypheic"), Some(MediaKind::Image));
+        assert_eq!(sniff_header(b"\0\0\0\x18ftypisom"), Some(MediaKind::Video));
+        assert_eq!(sniff_header(b"\0\0\0\x14ftypqt  "), Some(MediaKind::Video));
+        assert_eq!(
+            sniff_header(b"RIFF\0\0\0\0AVI LIST"),
+            Some(MediaKind::Video)
+        );
+        assert_eq!(
+            sniff_header(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(MediaKind::Image)
+        );
+        let mut mts = vec![0u8; 200];
+        mts[4] = b'G';
+        mts[196] = b'G';
+        assert_eq!(sniff_header(&mts), Some(MediaKind::Video));
+        assert_eq!(sniff_header(b"BMP is not a bitmap"), None);
+        assert_eq!(sniff_header(b"plain text"), None);
+        assert_eq!(sniff_header(b""), None);
+    }
+
+    #[test]
+    fn test_motion_energy() {
+        assert_eq!(motion_energy(&[0, 0, 0, 0], &[0, 0, 0, 0]), 0.0);
+        assert_eq!(motion_energy(&[0, 0, 255, 255], &[255, 255, 255, 255]), 0.5);
+    }
+
     #[test]
     fn test_model_config_save() {
         let model = ModelConfig {
@@ -486,10 +1428,13 @@ mod tests {
                 "Person".to_string(),
                 "Vehicle".to_string(),
             ]),
+            preprocess: Preprocess::default(),
+            input: InputTensor::default(),
         };
-        let toml_path = "models/md5va.toml";
-        model.save(toml_path).unwrap();
-        let target = load_model_config(toml_path).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("mdv5a.toml");
+        model.save(&toml_path).unwrap();
+        let target = load_model_config(&toml_path).unwrap();
         assert_eq!(model, target);
     }
 
@@ -504,6 +1449,8 @@ mod tests {
                 "Person".to_string(),
                 "Vehicle".to_string(),
             ]),
+            preprocess: Preprocess::default(),
+            input: InputTensor::default(),
         };
         let target = HashMap::from([
             (0, "Animal".to_string()),
@@ -513,9 +1460,47 @@ mod tests {
         assert_eq!(model.class_map(), target);
     }
 
+    #[test]
+    fn test_model_config_preprocess() {
+        let model: ModelConfig = toml::from_str(
+            r#"
+            name = "mdv5a"
+            path = "models/md_v5a_d_pp.onnx"
+            imgsz = 1280
+            classes = ["Animal", "Person", "Vehicle"]
+
+            [preprocess]
+            resize = "lanczos"
+            align = "top_left"
+
+            [input]
+            dtype = "uint8"
+            layout = "nhwc"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            model.input,
+            InputTensor {
+                dtype: InputDtype::Uint8,
+                layout: InputLayout::Nhwc,
+                normalize: true,
+            }
+        );
+        assert_eq!(model.input.shape(2, 640), [2, 640, 640, 3]);
+        assert_eq!(
+            model.preprocess,
+            Preprocess {
+                resize: ResizeFilter::Lanczos,
+                pad_color: [114, 114, 114],
+                align: PadAlign::TopLeft,
+            }
+        );
+    }
+
     #[test]
     fn test_load_model_config() {
-        let model = load_model_config("models/mdv5a.toml").unwrap();
+        let model = load_model_config("models/md_v5a.toml").unwrap();
         let target = ModelConfig {
             name: "mdv5a".to_string(),
             path: PathBuf::from("models/md_v5a_d_pp.onnx"),
@@ -525,6 +1510,8 @@ mod tests {
                 "Person".to_string(),
                 "Vehicle".to_string(),
             ]),
+            preprocess: Preprocess::default(),
+            input: InputTensor::default(),
         };
         assert_eq!(model, target);
     }
@@ -0,0 +1,255 @@
+//! Synthetic media fixtures and a runner for the md5rs binary with the mock backend
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, Rgb, RgbImage};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+/// Input size of the test model, small to keep preprocessing fast
+pub const IMGSZ: usize = 64;
+
+/// EXIF fields written into synthetic JPEG images
+#[derive(Default)]
+pub struct Exif<'a> {
+    pub make: Option<&'a str>,
+    pub model: Option<&'a str>,
+    /// `YYYY:MM:DD HH:MM:SS`
+    pub date_time_original: Option<&'a str>,
+}
+
+enum Field {
+    Ascii(String),
+    Long(u32),
+}
+
+/// Little endian TIFF IFD at `offset`, followed by the values that don't fit in an entry
+fn ifd(entries: &[(u16, Field)], offset: u32) -> Vec<u8> {
+    let mut data_offset = offset + 2 + entries.len() as u32 * 12 + 4;
+    let mut head = (entries.len() as u16).to_le_bytes().to_vec();
+    let mut data = Vec::new();
+    for (tag, field) in entries {
+        head.extend(tag.to_le_bytes());
+        match field {
+            Field::Ascii(text) => {
+                let mut bytes = text.as_bytes().to_vec();
+                bytes.push(0);
+                head.extend(2u16.to_le_bytes());
+                head.extend((bytes.len() as u32).to_le_bytes());
+                if bytes.len() <= 4 {
+                    bytes.resize(4, 0);
+                    head.extend(&bytes);
+                } else {
+                    head.extend(data_offset.to_le_bytes());
+                    data_offset += bytes.len() as u32;
+                    data.extend(bytes);
+                }
+            }
+            Field::Long(value) => {
+                head.extend(4u16.to_le_bytes());
+                head.extend(1u32.to_le_bytes());
+                head.extend(value.to_le_bytes());
+            }
+        }
+    }
+    head.extend(0u32.to_le_bytes());
+    head.extend(data);
+    head
+}
+
+/// APP1 segment payload with IFD0 and, for the shoot time, an Exif IFD
+fn exif_payload(exif: &Exif) -> Vec<u8> {
+    let mut ifd0 = Vec::new();
+    if let Some(make) = exif.make {
+        ifd0.push((0x010f, Field::Ascii(make.to_string())));
+    }
+    if let Some(model) = exif.model {
+        ifd0.push((0x0110, Field::Ascii(model.to_string())));
+    }
+    let exif_ifd: Vec<(u16, Field)> = exif
+        .date_time_original
+        .map(|time| vec![(0x9003, Field::Ascii(time.to_string()))])
+        .unwrap_or_default();
+    if !exif_ifd.is_empty() {
+        ifd0.push((0x8769, Field::Long(0)));
+    }
+    // The Exif IFD pointer is stored in its entry, so IFD0 has the same size either way
+    let exif_offset = 8 + ifd(&ifd0, 8).len() as u32;
+    if let Some((_, pointer)) = ifd0.iter_mut().find(|(tag, _)| *tag == 0x8769) {
+        *pointer = Field::Long(exif_offset);
+    }
+
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    tiff.extend(ifd(&ifd0, 8));
+    if !exif_ifd.is_empty() {
+        tiff.extend(ifd(&exif_ifd, exif_offset));
+    }
+    let mut payload = b"Exif\0\0".to_vec();
+    payload.extend(tiff);
+    payload
+}
+
+/// Gradient image, so every fixture decodes to something other than a flat color
+pub fn gradient(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+    })
+}
+
+pub fn write_jpeg(path: &Path, width: u32, height: u32, exif: &Exif) {
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, 90)
+        .encode_image(&gradient(width, height))
+        .unwrap();
+    let payload = exif_payload(exif);
+    let mut app1 = vec![0xff, 0xe1];
+    app1.extend((payload.len() as u16 + 2).to_be_bytes());
+    app1.extend(payload);
+    // Insert right after the SOI marker
+    jpeg.splice(2..2, app1);
+    std::fs::write(path, jpeg).unwrap();
+}
+
+pub fn write_png(path: &Path, width: u32, height: u32) {
+    gradient(width, height)
+        .save_with_format(path, ImageFormat::Png)
+        .unwrap();
+}
+
+pub fn has_ffmpeg() -> bool {
+    ["ffmpeg", "ffprobe"].iter().all(|bin| {
+        Command::new(bin)
+            .arg("-version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    })
+}
+
+/// Test pattern video of `duration` seconds at 5 fps with a key frame every frame
+pub fn write_video(path: &Path, duration: u32) {
+    let status = Command::new("ffmpeg")
+        .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+        .arg(format!("testsrc=size=96x64:rate=5:duration={}", duration))
+        .args(["-g", "1", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .status()
+        .unwrap();
+    assert!(status.success(), "ffmpeg failed to write {:?}", path);
+}
+
+/// Temporary workspace with a `media` folder, a model config and a mock backend script
+pub struct Workspace {
+    pub dir: TempDir,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("media")).unwrap();
+        std::fs::write(
+            dir.path().join("model.toml"),
+            format!(
+                "name = \"mock\"\npath = \"mock.onnx\"\nimgsz = {}\nclasses = [\"Animal\", \"Person\", \"Vehicle\"]\n",
+                IMGSZ
+            ),
+        )
+        .unwrap();
+        let workspace = Workspace { dir };
+        workspace.script(json!({}));
+        workspace
+    }
+
+    pub fn media(&self) -> PathBuf {
+        self.dir.path().join("media")
+    }
+
+    /// Replace the mock backend script, `{"default": [bbox], "files": {"name": [bbox]}}`
+    pub fn script(&self, script: Value) {
+        std::fs::write(
+            self.dir.path().join("mock.json"),
+            serde_json::to_string(&script).unwrap(),
+        )
+        .unwrap();
+    }
+
+    /// Run md5rs on the media folder with the mock backend, without checking it succeeded
+    pub fn try_run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_md5rs"))
+            .current_dir(self.dir.path())
+            .args(["--folder", "media", "--model", "model.toml"])
+            .args(["--mock-backend", "mock.json", "--log-file", "md5rs.log"])
+            .args(["--workers", "1", "--batch", "2"])
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Run md5rs on the media folder with the mock backend
+    pub fn run(&self, args: &[&str]) -> Output {
+        let output = self.try_run(args);
+        assert!(
+            output.status.success(),
+            "md5rs failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Run md5rs with only `args`, e.g. a subcommand or another folder than `media`
+    pub fn run_command(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_md5rs"))
+            .current_dir(self.dir.path())
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "md5rs failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Default export of the media folder, `media_result.json` or `media_result.csv` in the
+    /// working folder
+    pub fn result(&self, extension: &str) -> PathBuf {
+        self.dir.path().join(format!("media_result.{}", extension))
+    }
+
+    pub fn read_json(&self) -> Vec<Value> {
+        let json = std::fs::read_to_string(self.result("json")).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    pub fn read_csv(&self) -> Vec<csv::StringRecord> {
+        let mut reader = csv::Reader::from_path(self.result("csv")).unwrap();
+        reader.records().map(|record| record.unwrap()).collect()
+    }
+
+    pub fn csv_headers(&self) -> csv::StringRecord {
+        let mut reader = csv::Reader::from_path(self.result("csv")).unwrap();
+        reader.headers().unwrap().clone()
+    }
+}
+
+/// Mock box in model input pixels
+pub fn bbox(x1: f32, y1: f32, x2: f32, y2: f32, score: f32, class: usize) -> Value {
+    json!({"x1": x1, "y1": y1, "x2": x2, "y2": y2, "score": score, "class": class})
+}
+
+/// Exported frames of the file named `name`
+pub fn frames_of<'a>(frames: &'a [Value], name: &str) -> Vec<&'a Value> {
+    frames
+        .iter()
+        .filter(|frame| {
+            Path::new(frame["file_path"].as_str().unwrap())
+                .file_name()
+                .is_some_and(|file_name| file_name == name)
+        })
+        .collect()
+}
@@ -0,0 +1,163 @@
+//! Exact duplicates by content hash and near-duplicates in bursts with the mock backend
+
+mod common;
+
+use serde_json::json;
+
+use common::{bbox, frames_of, write_jpeg, write_png, Exif, Workspace};
+
+#[test]
+fn test_hash_duplicates() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    std::fs::create_dir(media.join("card_1")).unwrap();
+    std::fs::create_dir(media.join("card_2")).unwrap();
+    write_jpeg(
+        &media.join("card_1").join("a.jpg"),
+        96,
+        64,
+        &Exif::default(),
+    );
+    // The same card copied twice
+    std::fs::copy(
+        media.join("card_1").join("a.jpg"),
+        media.join("card_2").join("copy.jpg"),
+    )
+    .unwrap();
+    write_png(&media.join("card_2").join("b.png"), 64, 64);
+    // Only the first file of a content is detected, so the copy's script is never used
+    workspace.script(json!({
+        "default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)],
+        "files": {"copy.jpg": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 1)]}
+    }));
+
+    workspace.run(&["--export", "json", "--hash"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 3);
+    let original = frames_of(&frames, "a.jpg")[0];
+    let copy = frames_of(&frames, "copy.jpg")[0];
+    let other = frames_of(&frames, "b.png")[0];
+    assert_eq!(original["hash"].as_str().unwrap().len(), 32);
+    assert_eq!(copy["hash"], original["hash"]);
+    assert_ne!(other["hash"], original["hash"]);
+    assert!(original["duplicate_of"].is_null());
+    assert!(copy["duplicate_of"].as_str().unwrap().ends_with("a.jpg"));
+    assert_eq!(copy["label"][0], "Animal");
+    assert_eq!(copy["bboxes"], original["bboxes"]);
+}
+
+#[test]
+fn test_hash_duplicate_of_checkpoint() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    write_jpeg(&media.join("a.jpg"), 96, 64, &Exif::default());
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace.run(&["--export", "csv", "--hash"]);
+
+    // A copy added later reuses the detections of the exported file
+    std::fs::copy(media.join("a.jpg"), media.join("z.jpg")).unwrap();
+    workspace.script(json!({}));
+    workspace.run(&["--export", "csv", "--hash", "--incremental"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 2);
+    let column = |row: &csv::StringRecord, name: &str| {
+        let index = headers.iter().position(|h| h == name).unwrap();
+        row[index].to_string()
+    };
+    let copy = rows
+        .iter()
+        .find(|row| column(row, "file_path").ends_with("z.jpg"))
+        .unwrap();
+    assert_eq!(column(copy, "label"), "Animal");
+    assert!(column(copy, "duplicate_of").ends_with("a.jpg"));
+    assert_eq!(column(copy, "hash").len(), 32);
+}
+
+#[test]
+fn test_without_hash() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    write_jpeg(&media.join("a.jpg"), 96, 64, &Exif::default());
+    std::fs::copy(media.join("a.jpg"), media.join("b.jpg")).unwrap();
+    workspace.run(&["--export", "json"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 2);
+    assert!(frames
+        .iter()
+        .all(|frame| frame["hash"].is_null() && frame["duplicate_of"].is_null()));
+}
+
+/// Two shots of a burst five seconds apart and one an hour later
+fn burst(workspace: &Workspace) {
+    let media = workspace.media();
+    for (name, time) in [
+        ("burst_1.jpg", "2024:05:01 06:30:00"),
+        ("burst_2.jpg", "2024:05:01 06:30:05"),
+        ("later.jpg", "2024:05:01 07:30:00"),
+    ] {
+        let exif = Exif {
+            date_time_original: Some(time),
+            ..Exif::default()
+        };
+        write_jpeg(&media.join(name), 96, 64, &exif);
+    }
+    workspace.script(json!({
+        "default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)],
+        "files": {"burst_2.jpg": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 1)]}
+    }));
+}
+
+#[test]
+fn test_near_duplicates_flag() {
+    let workspace = Workspace::new();
+    burst(&workspace);
+    workspace.run(&["--export", "json", "--near-duplicates", "flag"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 3);
+    let frame = |name: &str| frames_of(&frames, name)[0];
+    // Every image is detected
+    assert_eq!(frame("burst_1.jpg")["label"][0], "Animal");
+    assert_eq!(frame("burst_2.jpg")["label"][0], "Person");
+    assert_eq!(
+        frame("burst_1.jpg")["perceptual_hash"],
+        frame("burst_2.jpg")["perceptual_hash"]
+    );
+    // Either may be detected first, the other one is flagged
+    let flagged: Vec<_> = ["burst_1.jpg", "burst_2.jpg"]
+        .into_iter()
+        .filter(|name| !frame(name)["near_duplicate_of"].is_null())
+        .collect();
+    assert_eq!(flagged.len(), 1);
+    assert!(frame("later.jpg")["near_duplicate_of"].is_null());
+}
+
+#[test]
+fn test_near_duplicates_skip() {
+    let workspace = Workspace::new();
+    burst(&workspace);
+    workspace.run(&["--export", "csv", "--near-duplicates", "skip"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 3);
+    let position = |column: &str| headers.iter().position(|h| h == column).unwrap();
+    let column = |name: &str, column: &str| {
+        let row = rows
+            .iter()
+            .find(|row| row[position("file_path")].ends_with(name))
+            .unwrap();
+        row[position(column)].to_string()
+    };
+    // Only one image of the burst is detected, the other gets its detections
+    let (first, copy) = if column("burst_1.jpg", "near_duplicate_of").is_empty() {
+        ("burst_1.jpg", "burst_2.jpg")
+    } else {
+        ("burst_2.jpg", "burst_1.jpg")
+    };
+    assert!(column(copy, "near_duplicate_of").ends_with(first));
+    assert_eq!(column(copy, "label"), column(first, "label"));
+    assert_eq!(column(copy, "bboxes"), column(first, "bboxes"));
+    assert_ne!(column(copy, "shoot_time"), column(first, "shoot_time"));
+    assert_eq!(column(copy, "perceptual_hash").len(), 16);
+    assert_eq!(column("later.jpg", "label"), "Animal");
+}
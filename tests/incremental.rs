@@ -0,0 +1,116 @@
+//! Incremental runs over a growing project tree with the mock backend
+
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use serde_json::json;
+
+use common::{bbox, frames_of, write_jpeg, write_png, Exif, Workspace};
+
+#[test]
+fn test_incremental_new_and_changed_files() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    std::fs::create_dir(media.join("2024-05")).unwrap();
+    write_jpeg(
+        &media.join("2024-05").join("a.jpg"),
+        96,
+        64,
+        &Exif::default(),
+    );
+    write_jpeg(
+        &media.join("2024-05").join("b.jpg"),
+        96,
+        64,
+        &Exif::default(),
+    );
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace.run(&["--export", "json"]);
+    assert_eq!(workspace.read_json().len(), 2);
+
+    // The next service visit adds a card folder sorted before the first one and b.jpg is
+    // replaced by a bigger image
+    std::fs::create_dir(media.join("2024-04")).unwrap();
+    write_png(&media.join("2024-04").join("c.png"), 64, 64);
+    write_jpeg(
+        &media.join("2024-05").join("b.jpg"),
+        192,
+        128,
+        &Exif::default(),
+    );
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 1)]}));
+    workspace.run(&["--export", "json", "--incremental"]);
+
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 3);
+    let label = |name: &str| {
+        let frames = frames_of(&frames, name);
+        assert_eq!(frames.len(), 1, "{}", name);
+        frames[0]["label"][0].as_str().unwrap().to_string()
+    };
+    // a.jpg is unchanged and keeps the detections of the first run
+    assert_eq!(label("a.jpg"), "Animal");
+    assert_eq!(label("b.jpg"), "Person");
+    assert_eq!(label("c.png"), "Person");
+}
+
+#[test]
+fn test_incremental_without_export() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    workspace.run(&["--export", "csv", "--incremental"]);
+    assert_eq!(workspace.read_csv().len(), 1);
+
+    // Nothing changed, so nothing is processed and the export stays the same
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace.run(&["--export", "csv", "--incremental"]);
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 1);
+    assert!(rows[0].iter().any(|field| field == "Blank"));
+}
+
+#[test]
+fn test_incremental_compares_hashes() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    let exif = |model| Exif {
+        model: Some(model),
+        ..Exif::default()
+    };
+    write_jpeg(&media.join("a.jpg"), 96, 64, &exif("CAM-A"));
+    write_jpeg(&media.join("b.jpg"), 96, 64, &exif("CAM-C"));
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace.run(&["--export", "json", "--hash"]);
+
+    // a.jpg is copied back with a new modification time, b.jpg is replaced by an image of the
+    // same size and modification time
+    let set_modified = |path: &std::path::Path, time| {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    };
+    let a = media.join("a.jpg");
+    std::fs::write(&a, std::fs::read(&a).unwrap()).unwrap();
+    set_modified(&a, SystemTime::now() + Duration::from_secs(3600));
+    let b = media.join("b.jpg");
+    let modified = std::fs::metadata(&b).unwrap().modified().unwrap();
+    write_jpeg(&b, 96, 64, &exif("CAM-B"));
+    set_modified(&b, modified);
+
+    // Thresholds may change between incremental runs
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 1)]}));
+    workspace.run(&[
+        "--export",
+        "json",
+        "--incremental",
+        "--hash",
+        "--conf",
+        "0.3",
+    ]);
+
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 2);
+    let label = |name: &str| frames_of(&frames, name)[0]["label"][0].as_str().unwrap();
+    assert_eq!(label("a.jpg"), "Animal");
+    assert_eq!(label("b.jpg"), "Person");
+}
@@ -0,0 +1,76 @@
+//! Include and exclude rules and dry runs of indexing
+
+mod common;
+
+use common::{write_png, Workspace};
+
+fn setup() -> Workspace {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    for folder in ["Blank Creek", "Blank Creek/Blank", "site_b"] {
+        std::fs::create_dir_all(media.join(folder)).unwrap();
+    }
+    write_png(&media.join("Blank Creek").join("a.png"), 64, 64);
+    write_png(
+        &media.join("Blank Creek").join("Blank").join("b.png"),
+        64,
+        64,
+    );
+    write_png(&media.join("site_b").join("c.png"), 64, 64);
+    std::fs::write(media.join("site_b").join("notes.txt"), "not media").unwrap();
+    workspace
+}
+
+fn listed(output: &std::process::Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let path = std::path::Path::new(line);
+            let folder = path.parent().unwrap().file_name().unwrap();
+            format!(
+                "{}/{}",
+                folder.to_string_lossy(),
+                path.file_name().unwrap().to_string_lossy()
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_dry_run() {
+    let workspace = setup();
+    let output = workspace.run(&["--export", "csv", "--dry-run"]);
+    // Organized class folders are left out by default, a site named after one isn't
+    assert_eq!(listed(&output), ["Blank Creek/a.png", "site_b/c.png"]);
+    assert!(!workspace.result("csv").exists());
+
+    let output = workspace.run(&[
+        "--export",
+        "csv",
+        "--dry-run",
+        "--no-default-excludes",
+        "--exclude",
+        "site_b",
+    ]);
+    // Listed in walk order, where the folder sorts before a.png
+    assert_eq!(listed(&output), ["Blank/b.png", "Blank Creek/a.png"]);
+}
+
+#[test]
+fn test_include_exclude() {
+    let workspace = setup();
+    workspace.run(&[
+        "--export",
+        "csv",
+        "--include",
+        "*.png",
+        "--exclude",
+        "Blank Creek/*.png",
+    ]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    let index = headers.iter().position(|h| h == "relative_path").unwrap();
+    let paths: Vec<_> = rows.iter().map(|row| row[index].to_string()).collect();
+    // notes.txt isn't included, so it isn't reported as unsupported either
+    assert_eq!(paths, ["site_b/c.png"]);
+}
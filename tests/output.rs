@@ -0,0 +1,88 @@
+//! Export location and templated names
+
+mod common;
+
+use common::{write_png, Workspace};
+
+fn media_entries(workspace: &Workspace) -> Vec<String> {
+    let mut entries: Vec<String> = std::fs::read_dir(workspace.media())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn test_output_template() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    workspace.run(&["--export", "csv", "--output", "results/{model}_{timestamp}"]);
+
+    // The input folder is left as it was
+    assert_eq!(media_entries(&workspace), ["a.png"]);
+    let results = workspace.media().parent().unwrap().join("results");
+    let names: Vec<String> = std::fs::read_dir(&results)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    assert_eq!(names.len(), 1);
+    assert!(names[0].starts_with("mock_20"), "{}", names[0]);
+    assert!(names[0].ends_with(".csv"));
+}
+
+#[test]
+fn test_output_in_folder() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    workspace.run(&["--export", "json", "--output", "media/"]);
+    assert_eq!(
+        media_entries(&workspace),
+        [".result.json.header.json", "a.png", "result.json"]
+    );
+
+    // Neither export is indexed as an unsupported file when processing the folder again
+    workspace.run(&["--export", "json", "--output", "media/custom.json"]);
+    let json = std::fs::read_to_string(workspace.media().join("custom.json")).unwrap();
+    let frames: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    let names: Vec<_> = frames
+        .iter()
+        .map(|frame| frame["relative_path"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["a.png"]);
+}
+
+#[test]
+fn test_output_of_other_folders() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    workspace.run(&["--export", "csv"]);
+
+    // Another set of folders writes to the same default export
+    let other = workspace.media().parent().unwrap().join("other");
+    std::fs::create_dir(&other).unwrap();
+    let output = workspace.try_run(&["--export", "csv", "--folder", "other"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--overwrite"));
+
+    workspace.run(&["--export", "csv", "--folder", "other", "--overwrite"]);
+    assert!(workspace.result("previous.csv").is_file());
+}
+
+#[test]
+fn test_invalid_settings_fail() {
+    let workspace = Workspace::new();
+    assert!(!workspace.try_run(&["--checkpoint", "0"]).status.success());
+    let output = workspace.try_run(&["--incremental", "--output", "{timestamp}"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_output_format_mismatch() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    let output = workspace.try_run(&["--export", "csv", "--output", "result.json"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("export format is csv"));
+}
@@ -0,0 +1,102 @@
+//! Relative paths in exports and remapping paths of moved folders
+
+mod common;
+
+use serde_json::json;
+
+use common::{bbox, write_png, Workspace};
+
+#[test]
+fn test_relative_paths() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    std::fs::create_dir(media.join("site_a")).unwrap();
+    write_png(&media.join("site_a").join("a.png"), 64, 64);
+    write_png(&media.join("b.png"), 64, 64);
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+
+    workspace.run(&["--export", "csv", "--relative-paths"]);
+    let headers = workspace.csv_headers();
+    let column = |row: &csv::StringRecord, name: &str| {
+        let index = headers.iter().position(|h| h == name).unwrap();
+        row[index].to_string()
+    };
+    let mut paths: Vec<_> = workspace
+        .read_csv()
+        .iter()
+        .map(|row| {
+            assert_eq!(column(row, "file_path"), column(row, "relative_path"));
+            assert_eq!(
+                column(row, "root"),
+                media.canonicalize().unwrap().to_string_lossy()
+            );
+            column(row, "file_path")
+        })
+        .collect();
+    paths.sort();
+    assert_eq!(paths, ["b.png", "site_a/a.png"]);
+
+    // Relative paths are joined onto the root on resume, so no file is processed again
+    workspace.script(json!({}));
+    workspace.run(&[
+        "--export",
+        "csv",
+        "--relative-paths",
+        "--resume-from",
+        "media_result.csv",
+    ]);
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 2);
+    assert!(rows.iter().all(|row| column(row, "label") == "Animal"));
+}
+
+#[test]
+fn test_remap() {
+    let workspace = Workspace::new();
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace.run(&["--export", "json"]);
+    let old_root = workspace.media().canonicalize().unwrap();
+
+    // The drive is mounted elsewhere
+    let mount = workspace.dir.path().join("mnt");
+    std::fs::create_dir(&mount).unwrap();
+    std::fs::rename(workspace.media(), mount.join("media")).unwrap();
+    let new_root = mount.join("media").canonicalize().unwrap();
+    workspace.run_command(&[
+        "remap",
+        "--log-file",
+        "md5rs.log",
+        "media_result.json",
+        &old_root.to_string_lossy(),
+        &new_root.to_string_lossy(),
+    ]);
+    let frames = workspace.read_json();
+    assert_eq!(frames[0]["root"], new_root.to_string_lossy().as_ref());
+    assert_eq!(
+        frames[0]["file_path"],
+        new_root.join("a.png").to_string_lossy().as_ref()
+    );
+
+    // The remapped header matches the moved folder, so it resumes without `--force-resume`
+    workspace.script(json!({}));
+    workspace.run_command(&[
+        "--folder",
+        "mnt/media",
+        "--model",
+        "model.toml",
+        "--mock-backend",
+        "mock.json",
+        "--log-file",
+        "md5rs.log",
+        "--export",
+        "json",
+        "--output",
+        "media_result",
+        "--resume-from",
+        "media_result.json",
+    ]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0]["label"][0], "Animal");
+}
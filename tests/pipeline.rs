@@ -0,0 +1,151 @@
+//! Index, preprocess, detect with the mock backend and export synthetic media
+
+mod common;
+
+use serde_json::json;
+
+use common::{bbox, frames_of, has_ffmpeg, write_jpeg, write_png, write_video, Exif, Workspace};
+
+fn labels(frame: &serde_json::Value) -> Vec<&str> {
+    let mut labels: Vec<&str> = frame["label"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|label| label.as_str().unwrap())
+        .collect();
+    labels.sort();
+    labels
+}
+
+#[test]
+fn test_images_json() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    write_jpeg(
+        &media.join("deer.jpg"),
+        160,
+        120,
+        &Exif {
+            make: Some("RECONYX"),
+            model: Some("HC600 HYPERFIRE"),
+            date_time_original: Some("2024:05:01 06:30:00"),
+        },
+    );
+    write_png(&media.join("empty.png"), 80, 60);
+    std::fs::write(media.join("notes.txt"), "not media").unwrap();
+    // Model input pixels, the 160x120 image is scaled by 0.4 and padded by 8 pixels at the top
+    workspace.script(json!({
+        "files": {
+            "deer.jpg": [
+                bbox(4.0, 12.0, 36.0, 48.0, 0.9, 0),
+                bbox(40.0, 12.0, 60.0, 32.0, 0.6, 1),
+                bbox(0.0, 0.0, 20.0, 20.0, 0.05, 2),
+            ]
+        }
+    }));
+
+    workspace.run(&["--export", "json"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 3);
+
+    let deer = frames_of(&frames, "deer.jpg");
+    assert_eq!(deer.len(), 1);
+    let deer = deer[0];
+    assert_eq!(deer["bboxes"].as_array().unwrap().len(), 2);
+    let corners: Vec<f64> = ["x1", "y1", "x2", "y2"]
+        .iter()
+        .map(|corner| deer["bboxes"][0][corner].as_f64().unwrap())
+        .collect();
+    for (corner, expected) in corners.iter().zip([10.0, 10.0, 90.0, 100.0]) {
+        assert!((corner - expected).abs() < 1e-3, "{:?}", corners);
+    }
+    assert_eq!(labels(deer), ["Animal", "Person"]);
+    assert_eq!(deer["make"], "RECONYX");
+    assert_eq!(deer["model"], "HC600 HYPERFIRE");
+    assert_eq!(deer["time_source"], "exif");
+    assert!(deer["shoot_time"]
+        .as_str()
+        .unwrap()
+        .starts_with("2024-05-01 06:30:00"));
+    assert_eq!(deer["frame_index"], 0);
+    assert_eq!(deer["total_frames"], 1);
+
+    let empty = frames_of(&frames, "empty.png")[0];
+    assert_eq!(labels(empty), ["Blank"]);
+    // Images without EXIF have no shoot time
+    assert!(empty["shoot_time"].is_null());
+    assert!(empty["time_source"].is_null());
+
+    let notes = frames_of(&frames, "notes.txt")[0];
+    assert_eq!(notes["error"], "skipped: unsupported");
+}
+
+#[test]
+fn test_images_csv() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    std::fs::create_dir(media.join("site_a")).unwrap();
+    write_jpeg(
+        &media.join("site_a").join("fox.jpg"),
+        120,
+        160,
+        &Exif::default(),
+    );
+    write_png(&media.join("site_a").join("empty.png"), 64, 64);
+    workspace.script(json!({
+        "default": [bbox(5.0, 5.0, 30.0, 30.0, 0.8, 0)],
+        "files": {"empty.png": []}
+    }));
+
+    workspace.run(&["--export", "csv"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 2);
+    let column = |row: &csv::StringRecord, name: &str| {
+        let index = headers.iter().position(|h| h == name).unwrap();
+        row[index].to_string()
+    };
+    let fox = rows
+        .iter()
+        .find(|row| column(row, "file_path").ends_with("fox.jpg"))
+        .unwrap();
+    assert_eq!(column(fox, "label"), "Animal");
+    assert!(column(fox, "bboxes").contains("\"score\":0.8"));
+    assert_eq!(column(fox, "error"), "");
+    let empty = rows
+        .iter()
+        .find(|row| column(row, "file_path").ends_with("empty.png"))
+        .unwrap();
+    assert_eq!(column(empty, "label"), "Blank");
+    assert_eq!(column(fox, "folder_id"), column(empty, "folder_id"));
+}
+
+#[test]
+#[ignore = "needs ffmpeg and ffprobe, run with `cargo test -- --ignored`"]
+fn test_video_frames() {
+    assert!(has_ffmpeg(), "ffmpeg and ffprobe not found");
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    write_video(&media.join("clip.mp4"), 2);
+    workspace.script(json!({
+        "files": {"clip.mp4": [bbox(10.0, 10.0, 40.0, 40.0, 0.7, 0)]}
+    }));
+
+    workspace.run(&["--export", "json", "--max-frames", "3"]);
+    let frames = workspace.read_json();
+    let clip = frames_of(&frames, "clip.mp4");
+    assert_eq!(clip.len(), 3);
+    let mut indexes: Vec<u64> = clip
+        .iter()
+        .map(|frame| frame["frame_index"].as_u64().unwrap())
+        .collect();
+    indexes.sort();
+    // 10 frames at 5 fps sampled evenly
+    assert_eq!(indexes, [0, 3, 6]);
+    for frame in clip {
+        assert_eq!(frame["total_frames"], 3);
+        assert_eq!(labels(frame), ["Animal"]);
+        assert!(frame["frame_time"].as_f64().unwrap() < 2.0);
+        assert!(frame["error"].is_null());
+    }
+}
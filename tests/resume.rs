@@ -0,0 +1,215 @@
+//! Resume from CSV checkpoints with the mock backend
+
+mod common;
+
+use serde_json::json;
+
+use common::{bbox, frames_of, write_jpeg, write_png, Exif, Workspace};
+
+fn column(headers: &csv::StringRecord, row: &csv::StringRecord, name: &str) -> String {
+    let index = headers.iter().position(|h| h == name).unwrap();
+    row[index].to_string()
+}
+
+fn setup() -> Workspace {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    write_jpeg(&media.join("a.jpg"), 96, 64, &Exif::default());
+    write_jpeg(&media.join("b.jpg"), 96, 64, &Exif::default());
+    write_png(&media.join("c.png"), 64, 96);
+    std::fs::write(media.join("notes.txt"), "not media").unwrap();
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+    workspace
+}
+
+#[test]
+fn test_resume_complete_checkpoint() {
+    let workspace = setup();
+    workspace.run(&["--export", "csv"]);
+    assert_eq!(workspace.read_csv().len(), 4);
+
+    // Nothing is left to detect, so a resumed run exports the checkpoint as is
+    workspace.script(json!({}));
+    workspace.run(&["--export", "csv", "--resume-from", "media_result.csv"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 4);
+    let skipped = rows
+        .iter()
+        .filter(|row| column(&headers, row, "error") == "skipped: unsupported")
+        .count();
+    assert_eq!(skipped, 1);
+    for row in rows
+        .iter()
+        .filter(|row| column(&headers, row, "error").is_empty())
+    {
+        assert_eq!(column(&headers, row, "label"), "Animal");
+    }
+}
+
+#[test]
+fn test_resume_partial_checkpoint() {
+    let workspace = setup();
+    workspace.run(&["--export", "csv"]);
+
+    // Keep only a.jpg in the checkpoint, as if the run stopped after it
+    let checkpoint = workspace.result("csv");
+    let text = std::fs::read_to_string(&checkpoint).unwrap();
+    let kept: Vec<&str> = text
+        .lines()
+        .enumerate()
+        .filter(|(i, line)| *i == 0 || line.contains("a.jpg"))
+        .map(|(_, line)| line)
+        .collect();
+    assert_eq!(kept.len(), 2);
+    std::fs::write(&checkpoint, kept.join("\n") + "\n").unwrap();
+
+    // Files detected again are blank with the new script, a.jpg keeps its checkpoint boxes
+    workspace.script(json!({}));
+    workspace.run(&["--export", "csv", "--resume-from", "media_result.csv"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 4);
+    for row in &rows {
+        let path = column(&headers, row, "file_path");
+        let label = column(&headers, row, "label");
+        if path.ends_with("a.jpg") {
+            assert_eq!(label, "Animal");
+        } else if path.ends_with("notes.txt") {
+            assert_eq!(column(&headers, row, "error"), "skipped: unsupported");
+        } else {
+            assert_eq!(label, "Blank", "{}", path);
+        }
+    }
+}
+
+#[test]
+fn test_resume_json() {
+    let workspace = setup();
+    workspace.run(&["--export", "json"]);
+    assert_eq!(workspace.read_json().len(), 4);
+
+    workspace.script(json!({}));
+    workspace.run(&["--export", "json", "--resume-from", "media_result.json"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 4);
+    assert!(frames
+        .iter()
+        .filter(|frame| frame["error"].is_null())
+        .all(|frame| frame["label"][0] == "Animal"));
+}
+
+#[test]
+fn test_resume_changed_settings() {
+    let workspace = setup();
+    workspace.run(&["--export", "csv"]);
+    // The header is hidden so indexing doesn't report it as an unsupported file
+    let header = workspace
+        .result("csv")
+        .with_file_name(".media_result.csv.header.json");
+    let header: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(header).unwrap()).unwrap();
+    assert_eq!(header["model"], "mock");
+
+    let output = workspace.try_run(&[
+        "--export",
+        "csv",
+        "--conf",
+        "0.5",
+        "--resume-from",
+        "media_result.csv",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("conf_thres"));
+
+    workspace.run(&[
+        "--export",
+        "csv",
+        "--conf",
+        "0.5",
+        "--resume-from",
+        "media_result.csv",
+        "--force-resume",
+    ]);
+    assert_eq!(workspace.read_csv().len(), 4);
+}
+
+#[test]
+fn test_resume_retry_errors() {
+    let workspace = setup();
+    let broken = workspace.media().join("d.jpg");
+    // A JPEG zeroed after its first marker, like a bad read from a card
+    write_jpeg(&broken, 96, 64, &Exif::default());
+    let jpeg = std::fs::read(&broken).unwrap();
+    let mut zeroed = jpeg.clone();
+    zeroed[4..].fill(0);
+    std::fs::write(&broken, &zeroed).unwrap();
+    let mtime = std::fs::metadata(&broken).unwrap().modified().unwrap();
+
+    workspace.run(&["--export", "json"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 5);
+    let error = |frames: &[serde_json::Value]| {
+        frames_of(frames, "d.jpg")[0]["error"]
+            .as_str()
+            .map(|e| e.to_string())
+    };
+    assert!(error(&frames).is_some());
+
+    // Restore the file with the same size and modification time, so it is the same file
+    // to resume and only processed again when errors are retried
+    std::fs::write(&broken, &jpeg).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&broken)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+
+    // Errors are kept by default
+    workspace.run(&["--export", "json", "--resume-from", "media_result.json"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 5);
+    assert!(error(&frames).is_some());
+
+    workspace.run(&[
+        "--export",
+        "json",
+        "--resume-from",
+        "media_result.json",
+        "--retry-errors",
+    ]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 5);
+    assert_eq!(error(&frames), None);
+    assert_eq!(frames_of(&frames, "d.jpg")[0]["label"][0], "Animal");
+    // Unsupported files are reported again, not duplicated
+    assert_eq!(frames_of(&frames, "notes.txt").len(), 1);
+}
+
+#[test]
+fn test_resume_added_and_changed_files() {
+    let workspace = setup();
+    workspace.run(&["--export", "csv"]);
+
+    // A file sorted first shifts every walk order id, and b.jpg is replaced by a larger image
+    let media = workspace.media();
+    write_png(&media.join("0.png"), 64, 64);
+    write_jpeg(&media.join("b.jpg"), 192, 128, &Exif::default());
+
+    workspace.script(json!({}));
+    workspace.run(&["--export", "csv", "--resume-from", "media_result.csv"]);
+    let headers = workspace.csv_headers();
+    let rows = workspace.read_csv();
+    assert_eq!(rows.len(), 5);
+    for row in &rows {
+        let relative_path = column(&headers, row, "relative_path");
+        let label = column(&headers, row, "label");
+        match relative_path.as_str() {
+            "a.jpg" | "c.png" => assert_eq!(label, "Animal"),
+            "0.png" | "b.jpg" => assert_eq!(label, "Blank"),
+            "notes.txt" => assert_eq!(column(&headers, row, "error"), "skipped: unsupported"),
+            other => panic!("unexpected file {}", other),
+        }
+    }
+}
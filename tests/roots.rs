@@ -0,0 +1,91 @@
+//! Multiple folders and file lists with the mock backend
+
+mod common;
+
+use serde_json::json;
+
+use common::{bbox, write_png, Workspace};
+
+#[test]
+fn test_multiple_folders() {
+    let workspace = Workspace::new();
+    let drive_b = workspace.media().parent().unwrap().join("drive_b");
+    std::fs::create_dir(&drive_b).unwrap();
+    // The same relative path under both folders
+    write_png(&workspace.media().join("a.png"), 64, 64);
+    write_png(&drive_b.join("a.png"), 64, 64);
+    workspace.script(json!({"default": [bbox(1.0, 1.0, 20.0, 20.0, 0.9, 0)]}));
+
+    workspace.run(&["--export", "json", "--folder", "drive_b"]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 2);
+    let mut roots: Vec<_> = frames
+        .iter()
+        .map(|frame| {
+            assert_eq!(frame["relative_path"], "a.png");
+            frame["root"].as_str().unwrap().to_string()
+        })
+        .collect();
+    roots.sort();
+    assert!(roots[0].ends_with("drive_b"));
+    assert!(roots[1].ends_with("media"));
+
+    // Files of both folders are matched by root on resume
+    workspace.script(json!({}));
+    workspace.run(&[
+        "--export",
+        "json",
+        "--folder",
+        "drive_b",
+        "--resume-from",
+        "media_result.json",
+    ]);
+    let frames = workspace.read_json();
+    assert_eq!(frames.len(), 2);
+    assert!(frames.iter().all(|frame| frame["label"][0] == "Animal"));
+}
+
+#[test]
+fn test_file_list() {
+    let workspace = Workspace::new();
+    let media = workspace.media();
+    let loose = media.parent().unwrap().join("loose");
+    std::fs::create_dir(media.join("site_a")).unwrap();
+    std::fs::create_dir(&loose).unwrap();
+    write_png(&media.join("site_a").join("a.png"), 64, 64);
+    write_png(&media.join("site_a").join("not_listed.png"), 64, 64);
+    write_png(&loose.join("b.png"), 64, 64);
+    std::fs::write(
+        media.parent().unwrap().join("list.txt"),
+        "media/site_a/a.png\nloose/b.png\n",
+    )
+    .unwrap();
+
+    workspace.run(&["--export", "csv", "--file-list", "list.txt"]);
+    let headers = workspace.csv_headers();
+    let column = |row: &csv::StringRecord, name: &str| {
+        let index = headers.iter().position(|h| h == name).unwrap();
+        row[index].to_string()
+    };
+    let mut files: Vec<_> = workspace
+        .read_csv()
+        .iter()
+        .map(|row| {
+            let root = column(row, "root");
+            let root = std::path::Path::new(&root).file_name().unwrap();
+            (
+                root.to_string_lossy().into_owned(),
+                column(row, "relative_path"),
+            )
+        })
+        .collect();
+    files.sort();
+    // Files under `--folder` are relative to it, others to their own folder
+    assert_eq!(
+        files,
+        [
+            ("loose".to_string(), "b.png".to_string()),
+            ("media".to_string(), "site_a/a.png".to_string())
+        ]
+    );
+}